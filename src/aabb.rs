@@ -0,0 +1,106 @@
+//! Axis aligned bounding boxes.
+
+use super::ray::*;
+use super::vec3::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(a: &Vec3, b: &Vec3) -> Self {
+        //accept the corners in any order
+        Aabb {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+    pub fn min(&self) -> &Vec3 {
+        &self.min
+    }
+    pub fn max(&self) -> &Vec3 {
+        &self.max
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+    pub fn extent(&self) -> Vec3 {
+        &self.max - &self.min
+    }
+    pub fn centroid(&self) -> Vec3 {
+        &(&self.min + &self.max) * 0.5
+    }
+    pub fn surface_area(&self) -> f32 {
+        let e = self.extent();
+        2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x())
+    }
+    pub fn longest_axis(&self) -> usize {
+        let e = self.extent();
+        if e.x() >= e.y() && e.x() >= e.z() {
+            0
+        } else if e.y() >= e.z() {
+            1
+        } else {
+            2
+        }
+    }
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+    /// the part of (t_min, t_max) where the ray is inside the box
+    pub fn clip(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> Option<(f32, f32)> {
+        //slab test: clip the ray's parameter range against each pair of axis aligned planes
+        let origin = ray.origin();
+        let direction = ray.direction();
+        for axis in 0..3 {
+            let inv_d = 1.0 / direction[axis];
+            let mut t0 = (self.min[axis] - origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            //f32::max/min drop NaNs which arise when the origin lies on a slab with a parallel ray
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hit() {
+        let aabb = Aabb::new(&Vec3::new(1.0, 1.0, 1.0), &Vec3::new(-1.0, -1.0, -1.0));
+        let towards = Ray::new(&Vec3::new(0.0, 0.0, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+        let away = Ray::new(&Vec3::new(0.0, 0.0, -5.0), &Vec3::new(0.0, 0.0, -1.0));
+        let beside = Ray::new(&Vec3::new(2.0, 0.0, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+        assert!(aabb.hit(&towards, 0.0, f32::INFINITY));
+        assert!(!aabb.hit(&towards, 0.0, 3.0));
+        assert!(!aabb.hit(&away, 0.0, f32::INFINITY));
+        assert!(!aabb.hit(&beside, 0.0, f32::INFINITY));
+    }
+
+    #[test]
+    fn test_surface_area() {
+        let aabb = Aabb::new(&Vec3::from_float(0.0), &Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(aabb.surface_area(), 22.0);
+        assert_eq!(aabb.longest_axis(), 2);
+    }
+}
@@ -0,0 +1,331 @@
+//! Bounding volume hierarchy built with the surface area heuristic.
+
+use super::aabb::*;
+use super::ray::*;
+use super::sampler::*;
+use super::shapes::*;
+use super::vec3::*;
+
+//number of centroid buckets evaluated per split when estimating the surface area heuristic
+const BINS: usize = 16;
+//nodes with at most this many objects may become leaves if splitting them does not pay off
+const MAX_LEAF_SIZE: usize = 4;
+//cost of a ray-box test relative to a ray-object test
+const TRAVERSAL_COST: f32 = 0.125;
+
+#[derive(Debug)]
+enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Branch {
+        bbox: Aabb,
+        left: usize,
+        right: usize,
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Branch { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// a bounding volume hierarchy over objects it owns
+#[derive(Debug)]
+pub struct Bvh<T: Intersect> {
+    nodes: Vec<BvhNode>,
+    objects: Vec<T>,
+    //objects without a bounding box (e.g. infinite planes) cannot be placed in the tree
+    unbounded: Vec<T>,
+}
+
+impl<T: Intersect> Bvh<T> {
+    pub fn new(objects: Vec<T>) -> Self {
+        let mut bounded = vec![];
+        let mut boxes = vec![];
+        let mut unbounded = vec![];
+        for object in objects {
+            match object.bounding_box() {
+                Some(bbox) => {
+                    boxes.push(bbox);
+                    bounded.push(object);
+                }
+                None => unbounded.push(object),
+            }
+        }
+        let centroids: Vec<Vec3> = boxes.iter().map(|bbox| bbox.centroid()).collect();
+        let mut order: Vec<usize> = (0..bounded.len()).collect();
+        let mut nodes = vec![];
+        if !order.is_empty() {
+            build(&mut nodes, &boxes, &centroids, &mut order, 0);
+        }
+        //lay the objects out in leaf order so every leaf refers to a contiguous range
+        let mut slots: Vec<Option<T>> = bounded.into_iter().map(Some).collect();
+        let objects = order
+            .iter()
+            .map(|&i| slots[i].take().expect("object placed in two leaves"))
+            .collect();
+        Bvh {
+            nodes,
+            objects,
+            unbounded,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.objects.iter().chain(self.unbounded.iter())
+    }
+
+    fn intersect_node(
+        &self,
+        node: usize,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        match &self.nodes[node] {
+            BvhNode::Leaf { bbox, start, end } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                closest_hit(&self.objects[*start..*end], ray, t_min, t_max, sampler)
+            }
+            BvhNode::Branch {
+                bbox,
+                left,
+                right,
+                axis,
+            } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                //visit the child nearer to the ray origin first so the far child can be culled by the hit distance
+                let (first, second) = if ray.direction()[*axis] < 0.0 {
+                    (*right, *left)
+                } else {
+                    (*left, *right)
+                };
+                let first_hit = self.intersect_node(first, ray, t_min, t_max, sampler);
+                let t_max = first_hit.as_ref().map_or(t_max, |hit| hit.distance);
+                self.intersect_node(second, ray, t_min, t_max, sampler)
+                    .or(first_hit)
+            }
+        }
+    }
+}
+
+impl<T: Intersect> Intersect for Bvh<T> {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let ret = if self.nodes.is_empty() {
+            None
+        } else {
+            self.intersect_node(0, ray, t_min, t_max, sampler)
+        };
+        let t_max = ret.as_ref().map_or(t_max, |hit| hit.distance);
+        closest_hit(&self.unbounded, ray, t_min, t_max, sampler).or(ret)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| *node.bbox())
+    }
+}
+
+fn closest_hit<'a, T: Intersect>(
+    objects: &'a [T],
+    ray: &Ray,
+    t_min: f32,
+    mut t_max: f32,
+    sampler: &mut Sampler,
+) -> Option<Hit<'a>> {
+    let mut ret = None;
+    for object in objects {
+        //each hit shrinks the search interval so later objects only report closer hits
+        if let Some(hit) = object.intersect(ray, t_min, t_max, sampler) {
+            t_max = hit.distance;
+            ret = Some(hit);
+        }
+    }
+    ret
+}
+
+fn build(
+    nodes: &mut Vec<BvhNode>,
+    boxes: &[Aabb],
+    centroids: &[Vec3],
+    order: &mut [usize],
+    start: usize,
+) -> usize {
+    let bbox = order[1..]
+        .iter()
+        .fold(boxes[order[0]], |acc, &i| acc.union(&boxes[i]));
+    let index = nodes.len();
+    nodes.push(BvhNode::Leaf {
+        bbox,
+        start,
+        end: start + order.len(),
+    });
+    if order.len() == 1 {
+        return index;
+    }
+
+    //bin the objects by centroid along the axis where the centroids are most spread out
+    let centroid_bounds = order[1..].iter().fold(
+        Aabb::new(&centroids[order[0]], &centroids[order[0]]),
+        |acc, &i| acc.grow(&centroids[i]),
+    );
+    let axis = centroid_bounds.longest_axis();
+    let low = centroid_bounds.min()[axis];
+    let extent = centroid_bounds.extent()[axis];
+    if extent <= 0.0 {
+        //all centroids coincide so no split can separate them
+        return index;
+    }
+    let bin_of =
+        |i: usize| (((centroids[i][axis] - low) / extent * BINS as f32) as usize).min(BINS - 1);
+    let mut counts = [0usize; BINS];
+    let mut bin_boxes: [Option<Aabb>; BINS] = [None; BINS];
+    for &i in order.iter() {
+        let bin = bin_of(i);
+        counts[bin] += 1;
+        bin_boxes[bin] = Some(match bin_boxes[bin] {
+            Some(bin_box) => bin_box.union(&boxes[i]),
+            None => boxes[i],
+        });
+    }
+
+    //sweep from both ends to get the area and count on either side of every bin boundary
+    let mut left_area = [0.0; BINS - 1];
+    let mut left_count = [0usize; BINS - 1];
+    let mut acc: Option<Aabb> = None;
+    let mut count = 0;
+    for bin in 0..BINS - 1 {
+        acc = union_option(acc, bin_boxes[bin]);
+        count += counts[bin];
+        left_area[bin] = acc.map_or(0.0, |bbox| bbox.surface_area());
+        left_count[bin] = count;
+    }
+    let mut best_split = 0;
+    let mut best_cost = f32::INFINITY;
+    let mut acc: Option<Aabb> = None;
+    let mut count = 0;
+    for bin in (1..BINS).rev() {
+        acc = union_option(acc, bin_boxes[bin]);
+        count += counts[bin];
+        let right_area = acc.map_or(0.0, |bbox| bbox.surface_area());
+        let cost = left_area[bin - 1] * left_count[bin - 1] as f32 + right_area * count as f32;
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = bin - 1;
+        }
+    }
+    let best_cost = TRAVERSAL_COST + best_cost / bbox.surface_area();
+    if order.len() <= MAX_LEAF_SIZE && best_cost >= order.len() as f32 {
+        return index;
+    }
+
+    //the lowest and highest centroids land in the first and last bins so both halves are non-empty
+    let mut mid = 0;
+    for i in 0..order.len() {
+        if bin_of(order[i]) <= best_split {
+            order.swap(i, mid);
+            mid += 1;
+        }
+    }
+    let (left_order, right_order) = order.split_at_mut(mid);
+    let left = build(nodes, boxes, centroids, left_order, start);
+    let right = build(nodes, boxes, centroids, right_order, start + mid);
+    nodes[index] = BvhNode::Branch {
+        bbox,
+        left,
+        right,
+        axis,
+    };
+    index
+}
+
+fn union_option(acc: Option<Aabb>, bbox: Option<Aabb>) -> Option<Aabb> {
+    match (acc, bbox) {
+        (Some(a), Some(b)) => Some(a.union(&b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::materials::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_matches_linear_scan() {
+        let mut sampler = Sampler::from_seed(0);
+        let mut rng = StdRng::seed_from_u64(7);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let spheres: Vec<Arc<Sphere>> = (0..500)
+            .map(|_| {
+                let center = Vec3::new(
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(-10.0, 10.0),
+                );
+                Arc::new(Sphere::new(
+                    &center,
+                    rng.gen_range(0.05, 0.5),
+                    material.clone(),
+                ))
+            })
+            .collect();
+        let mut linear = Intersectables::new(vec![]);
+        for sphere in &spheres {
+            linear.push(sphere.clone());
+        }
+        let bvh = Bvh::new(spheres.clone());
+        assert_eq!(bvh.len(), spheres.len());
+        for _ in 0..1000 {
+            let origin = Vec3::new(
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+            );
+            let direction = &Vec3::new(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            ) - &(&origin * 0.05);
+            let ray = Ray::new(&origin, &direction);
+            let expected = linear.intersect(&ray, 0.001, f32::INFINITY, &mut sampler);
+            let actual = bvh.intersect(&ray, 0.001, f32::INFINITY, &mut sampler);
+            assert_eq!(
+                expected.map(|hit| hit.distance),
+                actual.map(|hit| hit.distance)
+            );
+        }
+    }
+}
@@ -1,8 +1,11 @@
+//! The pinhole and thin lens camera that generates primary rays.
+
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
-use rand::Rng;
 use std::f32::consts::PI;
 
+/// a thin lens camera focused on the point it looks at
 #[derive(Debug)]
 pub struct Camera {
     origin: Vec3,
@@ -11,8 +14,10 @@ pub struct Camera {
     lower_left_corner: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f32,
+    //the shutter is open from time0 to time1
+    time0: f32,
+    time1: f32,
 }
 
 impl Camera {
@@ -38,28 +43,47 @@ impl Camera {
         let horizontal = &u * (half_width * 2.0);
         let vertical = &v * (half_height * 2.0);
         Camera {
-            origin: origin.clone(),
+            origin: *origin,
             horizontal,
             vertical,
             lower_left_corner,
             u,
             v,
-            w,
             lens_radius,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd: f32 = rand::thread_rng().gen_range(0.0, 2.0 * PI);
+    /// rays are cast at times drawn uniformly from [open, close), open == close freezes motion
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
+
+    pub fn shutter(&self) -> (f32, f32) {
+        (self.time0, self.time1)
+    }
+
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut Sampler) -> Ray {
+        let rd: f32 = sampler.gen_range(0.0, 2.0 * PI);
         let (x, y) = (u * rd.cos(), v * rd.sin());
 
         let mut offset = &(&self.u * x) + &(&self.v * y);
         offset = &offset * self.lens_radius;
         let source = &self.origin + &offset;
-        Ray::new(
+        //only draw a time when the shutter is open so still images use the same random numbers
+        let time = if self.time1 > self.time0 {
+            sampler.gen_range(self.time0, self.time1)
+        } else {
+            self.time0
+        };
+        Ray::at_time(
             &source,
             &(&(&(&self.lower_left_corner + &(&self.horizontal * u)) + &(&self.vertical * v))
                 - &source),
+            time,
         )
     }
 }
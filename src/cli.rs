@@ -0,0 +1,211 @@
+use clap::{App, Arg, ArgMatches};
+use ray_tracing_in_a_weekend::scene::*;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+//the scene file is optional, without one the built in random spheres scene is rendered
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub scene: Option<PathBuf>,
+    pub overrides: Overrides,
+}
+
+fn is_positive(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a positive whole number, got '{}'", value)),
+    }
+}
+
+fn is_number(value: String) -> Result<(), String> {
+    value
+        .parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| format!("expected a whole number, got '{}'", value))
+}
+
+fn is_seed(value: String) -> Result<(), String> {
+    value
+        .parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| format!("expected a 64 bit whole number, got '{}'", value))
+}
+
+//'800x400'
+fn is_resolution(value: String) -> Result<(), String> {
+    parse_resolution(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT such as 800x400, got '{}'", value))
+}
+
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+pub fn app() -> App<'static, 'static> {
+    App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Renders a scene file, or the random spheres demo when none is given")
+        .arg(
+            Arg::with_name("scene")
+                .value_name("SCENE")
+                .help("TOML scene file to render"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Image to write, the format is picked from the extension (.png, .ppm, .hdr)"),
+        )
+        .arg(
+            Arg::with_name("resolution")
+                .short("r")
+                .long("resolution")
+                .value_name("WIDTHxHEIGHT")
+                .validator(is_resolution)
+                .conflicts_with_all(&["width", "height"])
+                .help("Image size in pixels, e.g. 800x400"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("PIXELS")
+                .validator(is_positive)
+                .help("Image width in pixels"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .value_name("PIXELS")
+                .validator(is_positive)
+                .help("Image height in pixels"),
+        )
+        .arg(
+            Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .value_name("N")
+                .validator(is_positive)
+                .help("Samples per pixel"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .short("d")
+                .long("max-depth")
+                .value_name("N")
+                .validator(is_number)
+                .help("Maximum number of bounces per path"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("N")
+                .validator(is_seed)
+                .help("Seed for all random decisions, the same seed gives the same image"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("j")
+                .long("threads")
+                .value_name("N")
+                .validator(is_positive)
+                .help("Number of render threads, defaults to one per core"),
+        )
+}
+
+//values have already been checked by the validators so parsing cannot fail
+fn value<T: FromStr>(matches: &ArgMatches, name: &str) -> Option<T> {
+    matches.value_of(name).and_then(|value| value.parse().ok())
+}
+
+impl Options {
+    pub fn from_matches(matches: &ArgMatches) -> Options {
+        let (width, height) = match matches.value_of("resolution").and_then(parse_resolution) {
+            Some((width, height)) => (Some(width), Some(height)),
+            None => (value(matches, "width"), value(matches, "height")),
+        };
+        Options {
+            scene: matches.value_of("scene").map(PathBuf::from),
+            overrides: Overrides {
+                width,
+                height,
+                samples: value(matches, "samples"),
+                max_depth: value(matches, "max-depth"),
+                output: matches.value_of("output").map(PathBuf::from),
+                seed: value(matches, "seed"),
+                threads: value(matches, "threads"),
+            },
+        }
+    }
+
+    //prints usage and exits on bad arguments or --help
+    pub fn from_args() -> Options {
+        Options::from_matches(&app().get_matches())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, clap::Error> {
+        let args = std::iter::once("render").chain(args.iter().cloned());
+        app()
+            .get_matches_from_safe(args)
+            .map(|matches| Options::from_matches(&matches))
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(parse(&[]).unwrap(), Options::default());
+        let options = parse(&[
+            "scenes/cornell.toml",
+            "-o",
+            "out.hdr",
+            "-r",
+            "64x32",
+            "-s",
+            "8",
+            "--max-depth",
+            "0",
+            "--seed",
+            "7",
+            "-j",
+            "2",
+        ])
+        .unwrap();
+        assert_eq!(options.scene, Some(PathBuf::from("scenes/cornell.toml")));
+        assert_eq!(
+            options.overrides,
+            Overrides {
+                width: Some(64),
+                height: Some(32),
+                samples: Some(8),
+                max_depth: Some(0),
+                output: Some(PathBuf::from("out.hdr")),
+                seed: Some(7),
+                threads: Some(2),
+            }
+        );
+        let options = parse(&["--width", "10"]).unwrap();
+        assert_eq!(
+            (options.overrides.width, options.overrides.height),
+            (Some(10), None)
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_values() {
+        assert!(parse(&["--samples", "0"]).is_err());
+        assert!(parse(&["--threads", "many"]).is_err());
+        assert!(parse(&["--width", "99999999999"]).is_err());
+        assert!(parse(&["-r", "800"]).is_err());
+        assert!(parse(&["-r", "8x4", "--width", "10"]).is_err());
+        assert!(parse(&["a.toml", "b.toml"]).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+//! A path tracer following "Ray Tracing in One Weekend" and beyond.
+//!
+//! Scenes can be read from TOML files with [`scene::Scene`] or built in code from
+//! [`shapes`] and [`materials`], then rendered into a linear [`output::Framebuffer`]:
+//!
+//! ```
+//! use ray_tracing_in_a_weekend::camera::Camera;
+//! use ray_tracing_in_a_weekend::materials::Lambertian;
+//! use ray_tracing_in_a_weekend::render::render;
+//! use ray_tracing_in_a_weekend::scene::{RenderSettings, Scene};
+//! use ray_tracing_in_a_weekend::shapes::Sphere;
+//! use ray_tracing_in_a_weekend::vec3::Vec3;
+//!
+//! fn build() -> Scene {
+//!     let settings = RenderSettings {
+//!         width: 16,
+//!         height: 8,
+//!         samples: 2,
+//!         ..RenderSettings::default()
+//!     };
+//!     let camera = Camera::new(
+//!         &Vec3::new(0.0, 0.0, 1.0),
+//!         &Vec3::new(0.0, 0.0, -1.0),
+//!         &Vec3::new(0.0, 1.0, 0.0),
+//!         60.0,
+//!         2.0,
+//!         0.0,
+//!     );
+//!     let mut scene = Scene::new(settings, camera);
+//!     let red = scene.add_material("red", Lambertian::new(&Vec3::new(0.8, 0.2, 0.2)));
+//!     scene.add(Sphere::new(&Vec3::new(0.0, 0.0, -1.0), 0.5, red));
+//!     scene
+//! }
+//!
+//! let scene = build();
+//! let framebuffer = render(&scene.settings, &scene.camera, &scene.world(), &scene.lights());
+//! assert_eq!((framebuffer.width(), framebuffer.height()), (16, 8));
+//! ```
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod lights;
+pub mod materials;
+pub mod matrix;
+pub mod microfacet;
+pub mod noise;
+pub mod obj;
+pub mod output;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod shapes;
+pub mod texture;
+pub mod tonemap;
+pub mod vec3;
+pub mod volume;
@@ -0,0 +1,70 @@
+//! The emitters that next event estimation aims shadow rays at.
+//!
+//! Only shapes that can sample points on themselves are lights here: spheres, triangles, quads,
+//! rects and boxes with an emissive material. Emitters in meshes, instances or in motion still
+//! light the scene, but only when a scattered ray happens to reach them.
+
+use super::ray::*;
+use super::sampler::*;
+use super::shapes::*;
+use super::vec3::*;
+use std::sync::Arc;
+
+/// a list of lights, each picked with equal chance
+#[derive(Debug, Clone, Default)]
+pub struct Lights {
+    lights: Vec<Arc<dyn Intersect>>,
+}
+
+impl Lights {
+    pub fn new(lights: Vec<Arc<dyn Intersect>>) -> Self {
+        Lights { lights }
+    }
+
+    /// the objects that say they are lights
+    pub fn from_objects(objects: &[Arc<dyn Intersect>]) -> Self {
+        Lights::new(
+            objects
+                .iter()
+                .filter(|object| object.is_light())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// a point on one of the lights to aim a shadow ray at from origin
+    pub fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index =
+            ((sampler.gen() * self.lights.len() as f32) as usize).min(self.lights.len() - 1);
+        self.lights[index].sample_point(origin, sampler)
+    }
+
+    /// the density over solid angle with which sample_point picks the point where ray hit, zero
+    /// unless that point is on one of the lights
+    pub fn pdf(&self, ray: &Ray, hit: &Hit, sampler: &mut Sampler) -> f32 {
+        //a light owns the hit when it is what the ray meets first at that distance
+        let tolerance = 1e-4 * hit.distance.max(1.0);
+        let total: f32 = self
+            .lights
+            .iter()
+            .filter(|light| {
+                light
+                    .intersect(ray, 0.001, hit.distance + tolerance, sampler)
+                    .is_some_and(|own| (own.distance - hit.distance).abs() <= tolerance)
+            })
+            .map(|light| light.pdf(ray, hit))
+            .sum();
+        total / self.lights.len() as f32
+    }
+}
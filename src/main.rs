@@ -1,66 +1,70 @@
-mod camera;
-mod materials;
-mod ray;
-mod shapes;
-mod vec3;
-use camera::*;
-use materials::*;
-use rand::Rng;
-use ray::*;
-use shapes::*;
-use std::f32::{consts, INFINITY};
-use std::fs::File;
-use std::io::Write;
-use vec3::*;
+mod cli;
+use cli::*;
+use ray_tracing_in_a_weekend::camera::*;
+use ray_tracing_in_a_weekend::materials::*;
+use ray_tracing_in_a_weekend::output::*;
+use ray_tracing_in_a_weekend::render::*;
+use ray_tracing_in_a_weekend::sampler::*;
+use ray_tracing_in_a_weekend::scene::*;
+use ray_tracing_in_a_weekend::shapes::*;
+use ray_tracing_in_a_weekend::vec3::*;
+use std::process;
+use std::sync::Arc;
 
-fn color(ray: &Ray, world: &Intersectables, max_hits: u32) -> Vec3 {
-    if max_hits == 0 {
-        return Vec3::from_float(0.0);
+fn save(settings: &RenderSettings, framebuffer: &Framebuffer) {
+    if let Err(error) = framebuffer.save(&settings.output, &settings.tonemap) {
+        eprintln!("{}", error);
+        process::exit(1);
     }
-    match world.intersect(ray, 0.001, INFINITY) {
-        Some(hit) => {
-            return hit.albedo() * &color(&hit.collide(&ray), &world, max_hits - 1);
-        }
-        _ => {
-            let t = 0.5 * (ray.direction().y() + 1.0);
-            return &(&Vec3::new(1.0, 1.0, 1.0) * (1.0 - t)) + &(&Vec3::new(0.5, 0.7, 1.0) * t);
-        }
-    };
 }
 
-fn main() {
-    let nx = 800;
-    let ny = 400;
-    let ns = 100;
-    let mut data = format!("P3\n{} {} \n255\n", nx, ny);
-    let material1 = Lambertian::new(&Vec3::new(0.8, 0.2, 0.5));
-    let material2 = Lambertian::new(&Vec3::new(0.5, 0.8, 0.0));
-    let material3 = Metal::new(&Vec3::new(0.5, 0.5, 0.5), 0.0);
-    let material4 = Dielectric::new(1.5, 0.0);
-    let sphere1 = Sphere::new(&Vec3::new(0.0, 1.0, -1.0), 1.0, &material1);
-    let sphere2 = Sphere::new(&Vec3::new(0.0, -1000.0, -1.0), 1000.0, &material2);
-    let sphere3 = Sphere::new(&Vec3::new(1.0, 1.0, -1.0), 1.0, &material3);
-    let sphere4 = Sphere::new(&Vec3::new(-1.0, 1.0, -1.0), 1.0, &material4);
-    let sphere5 = Sphere::new(&Vec3::new(-1.0, 1.0, -1.0), -0.98, &material4);
-    let mut world = Intersectables::new(vec![&sphere1, &sphere2, &sphere3, &sphere4, &sphere5]);
+//the random spheres scene rendered when no scene file is given
+fn demo_scene(settings: RenderSettings) -> Scene {
+    let camera = Camera::new(
+        &Vec3::new(3.0, 3.0, 2.0),
+        &Vec3::new(0.0, 0.0, -1.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        (settings.width as f32) / (settings.height as f32),
+        0.05,
+    );
+    let seed = settings.seed;
+    let mut scene = Scene::new(settings, camera);
+    let material1 = scene.add_material("pink", Lambertian::new(&Vec3::new(0.8, 0.2, 0.5)));
+    let material2 = scene.add_material("ground", Lambertian::new(&Vec3::new(0.5, 0.8, 0.0)));
+    let material3 = scene.add_material("mirror", Metal::new(&Vec3::new(0.5, 0.5, 0.5), 0.0));
+    let material4 = scene.add_material("glass", Dielectric::new(1.5, 0.0));
+    scene.add(Sphere::new(&Vec3::new(0.0, 1.0, -1.0), 1.0, material1));
+    scene.add(Sphere::new(
+        &Vec3::new(0.0, -1000.0, -1.0),
+        1000.0,
+        material2,
+    ));
+    scene.add(Sphere::new(&Vec3::new(1.0, 1.0, -1.0), 1.0, material3));
+    scene.add(Sphere::new(
+        &Vec3::new(-1.0, 1.0, -1.0),
+        1.0,
+        material4.clone(),
+    ));
+    scene.add(Sphere::new(&Vec3::new(-1.0, 1.0, -1.0), -0.98, material4));
 
-    let mut materials: Vec<Box<dyn Material>> =
-        vec![Box::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)))];
+    let mut materials: Vec<Arc<dyn Material>> =
+        vec![Arc::new(Lambertian::new(&Vec3::new(0.5, 0.5, 0.5)))];
 
-    let mut rng = rand::thread_rng();
+    let mut rng = Sampler::from_seed(seed);
     for _ in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f32 = rng.gen();
+        for _ in -11..11 {
+            let choose_mat = rng.gen();
             if choose_mat < 0.33 {
                 // println!("lamb");
-                materials.push(Box::new(Lambertian::new(&Vec3::new(
+                materials.push(Arc::new(Lambertian::new(&Vec3::new(
                     rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
                     rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
                     rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
                 ))));
             } else if choose_mat < 0.67 {
                 // println!("metal");
-                materials.push(Box::new(Metal::new(
+                materials.push(Arc::new(Metal::new(
                     &(&(&Vec3::new(
                         rng.gen_range(0.0, 1.0),
                         rng.gen_range(0.0, 1.0),
@@ -70,14 +74,13 @@ fn main() {
                     rng.gen_range(0.0, 1.0) * 0.5,
                 )));
             } else {
-                materials.push(Box::new(Dielectric::new(
+                materials.push(Arc::new(Dielectric::new(
                     rng.gen_range(1.3, 1.5),
                     rng.gen_range(0.0, 1.0) * 0.05,
                 )));
             }
         }
     }
-    let mut objects = vec![];
     let mut i = 0;
     for a in -11..11 {
         for b in -11..11 {
@@ -87,44 +90,35 @@ fn main() {
                 radius,
                 (b as f32) + 0.9 * rng.gen_range(0.0, 1.0),
             );
-            objects.push(Sphere::new(&center, radius, &*materials[i]));
+            scene.add(Sphere::new(&center, radius, materials[i].clone()));
             i += 1;
         }
     }
-    for i in 0..objects.len() {
-        world.push(&objects[i]);
-    }
-    let camera = Camera::new(
-        &Vec3::new(3.0, 3.0, 2.0),
-        &Vec3::new(0.0, 0.0, -1.0),
-        &Vec3::new(0.0, 1.0, 0.0),
-        90.0,
-        (nx as f32) / (ny as f32),
-        0.05,
-    );
+    scene
+}
 
-    for j in (0..ny).rev() {
-        for i in 0..nx {
-            let mut col = Vec3::new(0.0, 0.0, 0.0);
-            for _ in 0..ns {
-                let rr: f32 = rng.gen();
-                let u = ((i as f32) + rr) / (nx as f32);
-                let v = ((j as f32) + rr) / (ny as f32);
-                let r = camera.get_ray(u, v);
-                col += &color(&r, &world, 50);
+fn main() {
+    let options = Options::from_args();
+    let scene = match &options.scene {
+        Some(path) => Scene::load_with(path, &options.overrides).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        }),
+        None => {
+            let mut settings = RenderSettings::default();
+            options.overrides.apply(&mut settings);
+            if let Err(message) = settings.validate() {
+                eprintln!("{}", message);
+                process::exit(1);
             }
-            col /= ns as f32;
-            let ir = col.r();
-            let ig = col.g();
-            let ib = col.b();
-            data.push_str(&format!(
-                "{} {} {}\n",
-                ((255.99 * ir) as i32),
-                ((255.99 * ig) as i32),
-                ((255.99 * ib) as i32)
-            ));
+            demo_scene(settings)
         }
-    }
-    let mut f = File::create("test.ppm").expect("Unable to create file");
-    f.write_all(data.as_bytes()).expect("Unable to write data");
+    };
+    let framebuffer = render(
+        &scene.settings,
+        &scene.camera,
+        &scene.world(),
+        &scene.lights(),
+    );
+    save(&scene.settings, &framebuffer);
 }
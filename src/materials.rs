@@ -1,117 +1,297 @@
+//! How light scatters off surfaces.
+
+use super::microfacet::*;
 use super::ray::*;
+use super::sampler::*;
 use super::shapes::*;
+use super::texture::*;
+use super::tonemap::*;
 use super::vec3::*;
 use core::fmt::Debug;
-use rand::Rng;
 use std::f32::consts;
 
-fn rand_in_unit_sphere() -> Vec3 {
+fn rand_in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
     Vec3::from_spherical(
         1.0,
-        rand::thread_rng().gen_range(0.0, consts::PI),
-        rand::thread_rng().gen_range(0.0, 2.0 * consts::PI),
+        sampler.gen_range(0.0, consts::PI),
+        sampler.gen_range(0.0, 2.0 * consts::PI),
     )
 }
 
-pub trait Material: Debug {
-    fn collide(&self, ray_in: &Ray, hit: &Hit) -> Ray;
-    fn albedo(&self) -> &Vec3;
+//uniform over the sphere, unlike picking both angles uniformly which crowds the poles
+fn rand_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    let z = sampler.gen_range(-1.0, 1.0);
+    let phi = sampler.gen_range(0.0, 2.0 * consts::PI);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
-#[derive(Debug, Copy, Clone)]
+/// detail that tilts a surface's shading normal without changing its geometry
+#[derive(Debug)]
+pub enum SurfaceDetail {
+    /// a tangent space normal map, its channels in [0, 1] hold the components in [-1, 1] along
+    /// the tangent, bitangent and normal of the hit's shading frame
+    NormalMap(Box<dyn Texture>),
+    /// a height field, the normal leans away from uphill by strength times the slope in uv
+    BumpMap {
+        height: Box<dyn Texture>,
+        strength: f32,
+    },
+}
+
+//the least cosine between a mapped normal and the surface normal, steeper ones are pulled back
+const MIN_MAPPED_COSINE: f32 = 0.05;
+//step in uv, and in world units for solid textures, of the bump map's finite differences
+const BUMP_STEP: f32 = 1e-3;
+
+impl SurfaceDetail {
+    /// the mapped unit normal in world space, always on the same side of the surface as hit.normal
+    pub fn normal(&self, hit: &Hit) -> Vec3 {
+        let frame = &hit.shading;
+        let local = match self {
+            SurfaceDetail::NormalMap(map) => &(&hit.texture_value(&**map) * 2.0) - 1.0,
+            SurfaceDetail::BumpMap { height, strength } => {
+                let (u, v) = hit.uv;
+                let height_at = |du: f32, dv: f32| {
+                    let point = &(&hit.point + &(&frame.tangent * du)) + &(&frame.bitangent * dv);
+                    height.value(u + du, v + dv, &point).sum() / 3.0
+                };
+                let h = height_at(0.0, 0.0);
+                let slope_u = (height_at(BUMP_STEP, 0.0) - h) / BUMP_STEP;
+                let slope_v = (height_at(0.0, BUMP_STEP) - h) / BUMP_STEP;
+                Vec3::new(-strength * slope_u, -strength * slope_v, 1.0)
+            }
+        };
+        //a normal tilted to the horizon or past it would let light in under the surface
+        let sideways = (local.x() * local.x() + local.y() * local.y()).sqrt();
+        let min_z =
+            MIN_MAPPED_COSINE * sideways / (1.0 - MIN_MAPPED_COSINE * MIN_MAPPED_COSINE).sqrt();
+        let local = Vec3::new(local.x(), local.y(), local.z().max(min_z).max(1e-6));
+        frame.to_world(&local).normalize()
+    }
+}
+
+//the normal turned to face the side the ray came from
+fn facing(normal: &Vec3, ray_in: &Ray) -> Vec3 {
+    if normal.dot(ray_in.direction()) > 0.0 {
+        -*normal
+    } else {
+        *normal
+    }
+}
+
+//the normal to scatter about, a mapped normal is only used when the ray meets it from the same
+//side as the surface itself, otherwise reflections would head into the surface
+fn scattering_normal(detail: &Option<SurfaceDetail>, ray_in: &Ray, hit: &Hit) -> Vec3 {
+    match detail {
+        Some(detail) => {
+            let mapped = detail.normal(hit);
+            let direction = ray_in.direction();
+            if mapped.dot(direction) * hit.normal.dot(direction) > 0.0 {
+                mapped
+            } else {
+                hit.normal
+            }
+        }
+        None => hit.normal,
+    }
+}
+
+//a direction that a mapped normal tilted across the surface is mirrored back to the side it
+//belongs on, without a map directions are left as they were
+fn keep_side(detail: &Option<SurfaceDetail>, direction: Vec3, side: &Vec3) -> Vec3 {
+    let cosine = direction.dot(side);
+    if detail.is_none() || cosine >= 0.0 {
+        return direction;
+    }
+    &direction - &(side * (2.0 * cosine))
+}
+
+/// the ray leaving a surface and how much of the light it brings back is passed on
+#[derive(Debug)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// the bsdf times the cosine over the pdf, the weight a path tracer gives the scattered ray
+    pub attenuation: Vec3,
+    /// density over solid angle that the direction was drawn with, 0 for specular directions
+    pub pdf: f32,
+    /// mirrors and glass send light in a single direction, which no other sampling can find
+    pub specular: bool,
+}
+
+/// how light scatters off a surface
+pub trait Material: Debug + Send + Sync {
+    /// where ray_in goes after hitting the surface, None when the surface absorbs it
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord>;
+    /// the bsdf times the cosine for light arriving from direction and leaving back along ray_in,
+    /// black for materials that only scatter into single directions
+    fn eval(&self, _ray_in: &Ray, _hit: &Hit, _direction: &Vec3) -> Vec3 {
+        Vec3::from_float(0.0)
+    }
+    /// the density over solid angle with which scatter draws direction
+    fn pdf(&self, _ray_in: &Ray, _hit: &Hit, _direction: &Vec3) -> f32 {
+        0.0
+    }
+    /// light given off at the hit, most materials do not emit
+    fn emitted(&self, _hit: &Hit) -> Vec3 {
+        Vec3::from_float(0.0)
+    }
+    /// whether the material emits any light, which makes the shapes it is on worth sampling
+    fn emits(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Box<dyn Texture>,
+    detail: Option<SurfaceDetail>,
 }
 
 impl Lambertian {
-    pub fn albedo(&self) -> &Vec3 {
-        &self.albedo
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
     }
     pub fn new(albedo: &Vec3) -> Self {
+        Lambertian::textured(Box::new(SolidColor::new(albedo)))
+    }
+    pub fn textured(albedo: Box<dyn Texture>) -> Self {
         Lambertian {
-            albedo: albedo.clone(),
+            albedo,
+            detail: None,
         }
     }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
 }
 
 impl Material for Lambertian {
-    fn albedo(&self) -> &Vec3 {
-        &self.albedo
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        //open surfaces such as triangles can be hit from behind so scatter on the side the ray came from
+        let normal = facing(&scattering_normal(&self.detail, ray_in, hit), ray_in);
+        let side = facing(&hit.normal, ray_in);
+        //a point on the unit sphere around the tip of the normal gives directions in proportion
+        //to the cosine, which cancels the cosine and the 1/pi of the bsdf
+        let target = keep_side(&self.detail, &normal + &rand_unit_vector(sampler), &side);
+        //scattered rays stay at the same instant so moving objects line up along the path
+        let ray = Ray::at_time(&hit.point, &target, ray_in.time());
+        let pdf = ray.direction().dot(&normal).max(0.0) / consts::PI;
+        Some(ScatterRecord {
+            ray,
+            attenuation: hit.texture_value(&*self.albedo),
+            pdf,
+            specular: false,
+        })
     }
-    fn collide(&self, ray_in: &Ray, hit: &Hit) -> Ray {
-        let target = hit.normal().direction() + &rand_in_unit_sphere();
-        Ray::new(&hit.point, &target)
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        //albedo / pi times the cosine, which is the albedo times the pdf
+        &hit.texture_value(&*self.albedo) * self.pdf(ray_in, hit, direction)
+    }
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        //light from the far side of the surface never reaches this side
+        if direction.dot(&facing(&hit.normal, ray_in)) <= 0.0 {
+            return 0.0;
+        }
+        let normal = facing(&scattering_normal(&self.detail, ray_in, hit), ray_in);
+        direction.normalize().dot(&normal).max(0.0) / consts::PI
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
 pub struct Metal {
-    albedo: Vec3,
+    albedo: Box<dyn Texture>,
     pub fuzz: f32,
+    detail: Option<SurfaceDetail>,
 }
 
 impl Metal {
-    pub fn albedo(&self) -> &Vec3 {
-        &self.albedo
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
     }
     pub fn new(albedo: &Vec3, fuzz: f32) -> Self {
+        Metal::textured(Box::new(SolidColor::new(albedo)), fuzz)
+    }
+    pub fn textured(albedo: Box<dyn Texture>, fuzz: f32) -> Self {
         Metal {
-            albedo: albedo.clone(),
+            albedo,
             fuzz,
+            detail: None,
         }
     }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
 }
 
 impl Material for Metal {
-    fn albedo(&self) -> &Vec3 {
-        &self.albedo
-    }
-    fn collide(&self, ray_in: &Ray, hit: &Hit) -> Ray {
-        let normal = *hit.normal().direction();
-        Ray::new(&hit.point, &reflect(ray_in.direction(), &normal, self.fuzz))
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let normal = scattering_normal(&self.detail, ray_in, hit);
+        let reflected = reflect(ray_in.direction(), &normal, self.fuzz, sampler);
+        //fuzz blurs the reflection without a density to go with it, so it counts as specular too
+        Some(ScatterRecord {
+            ray: Ray::at_time(
+                &hit.point,
+                &keep_side(&self.detail, reflected, &facing(&hit.normal, ray_in)),
+                ray_in.time(),
+            ),
+            attenuation: hit.texture_value(&*self.albedo),
+            pdf: 0.0,
+            specular: true,
+        })
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug)]
 pub struct Dielectric {
-    albedo: Vec3,
+    albedo: Box<dyn Texture>,
     pub refraction_index: f32,
     pub fuzz: f32,
+    detail: Option<SurfaceDetail>,
 }
 
 impl Dielectric {
-    pub fn albedo(&self) -> &Vec3 {
-        &self.albedo
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
     }
     pub fn new(refraction_index: f32, fuzz: f32) -> Self {
+        Dielectric::textured(
+            Box::new(SolidColor::new(&Vec3::from_float(1.0))),
+            refraction_index,
+            fuzz,
+        )
+    }
+    /// the texture tints everything passing through or reflecting off the surface
+    pub fn textured(albedo: Box<dyn Texture>, refraction_index: f32, fuzz: f32) -> Self {
         Dielectric {
-            albedo: Vec3::from_float(1.0),
+            albedo,
             refraction_index,
             fuzz,
+            detail: None,
         }
     }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
 }
 
 impl Material for Dielectric {
-    fn albedo(&self) -> &Vec3 {
-        &self.albedo
-    }
-    fn collide(&self, ray_in: &Ray, hit: &Hit) -> Ray {
-        let normal = hit.normal();
-        let proj_length = normal.direction().dot(ray_in.direction());
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let normal = scattering_normal(&self.detail, ray_in, hit);
+        let side = facing(&hit.normal, ray_in);
+        let proj_length = normal.dot(ray_in.direction());
         let (outward_normal, ni_over_nt, cosine) = if proj_length > 0.0 {
-            (-*normal.direction(), self.refraction_index, {
+            (-normal, self.refraction_index, {
                 (1.0 - self.refraction_index
                     * self.refraction_index
                     * (1.0 - proj_length * proj_length))
                     .sqrt()
             })
         } else {
-            (
-                *normal.direction(),
-                1.0 / self.refraction_index,
-                -proj_length,
-            )
+            (normal, 1.0 / self.refraction_index, -proj_length)
         };
 
         let refracted = refract(ray_in.direction(), &outward_normal, ni_over_nt);
@@ -123,13 +303,615 @@ impl Material for Dielectric {
                 reflect_prob = ret;
             }
         }
-        if rand::thread_rng().gen::<f32>() < reflect_prob {
-            return Ray::new(
-                &hit.point,
-                &reflect(ray_in.direction(), &outward_normal, self.fuzz),
-            );
+        let direction = if sampler.gen() < reflect_prob {
+            let reflected = reflect(ray_in.direction(), &outward_normal, self.fuzz, sampler);
+            keep_side(&self.detail, reflected, &side)
+        } else {
+            keep_side(&self.detail, refracted.unwrap(), &-side)
+        };
+        //choosing between reflection and refraction by the fresnel term leaves only the tint
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &direction, ray_in.time()),
+            attenuation: hit.texture_value(&*self.albedo),
+            pdf: 0.0,
+            specular: true,
+        })
+    }
+}
+
+//the shading frame around the normal facing the incoming ray, tilted by any normal or bump map
+fn local_frame(detail: &Option<SurfaceDetail>, ray_in: &Ray, hit: &Hit) -> Onb {
+    let normal = facing(&scattering_normal(detail, ray_in, hit), ray_in);
+    Onb::from_normal_tangent(&normal, &hit.shading.tangent)
+}
+
+/// a metal with a GGX microfacet surface, colored by the fresnel term of its complex index of
+/// refraction
+#[derive(Debug)]
+pub struct RoughConductor {
+    bsdf: ConductorBsdf,
+    detail: Option<SurfaceDetail>,
+}
+
+impl RoughConductor {
+    /// roughness runs from 0, a mirror, to 1
+    pub fn new(eta: &Vec3, k: &Vec3, roughness: f32) -> Self {
+        RoughConductor {
+            bsdf: ConductorBsdf {
+                distribution: Ggx::from_roughness(roughness),
+                eta: *eta,
+                k: *k,
+            },
+            detail: None,
+        }
+    }
+    /// one of the metals in `microfacet::CONDUCTORS`
+    pub fn named(name: &str, roughness: f32) -> Option<Self> {
+        let (eta, k) = conductor_ior(name)?;
+        Some(RoughConductor::new(&eta, &k, roughness))
+    }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+    pub fn bsdf(&self) -> &ConductorBsdf {
+        &self.bsdf
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let frame = local_frame(&self.detail, ray_in, hit);
+        let wo = frame.to_local(&-*ray_in.direction());
+        let wi = self.bsdf.sample(&wo, sampler)?;
+        let direction = frame.to_world(&wi);
+        //a tilted shading frame can reflect into the surface, which then absorbs the ray
+        let pdf = self.bsdf.pdf(&wo, &wi);
+        if direction.dot(&facing(&hit.normal, ray_in)) <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &direction, ray_in.time()),
+            attenuation: &self.bsdf.eval(&wo, &wi) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        if direction.dot(&facing(&hit.normal, ray_in)) <= 0.0 {
+            return Vec3::from_float(0.0);
+        }
+        let frame = local_frame(&self.detail, ray_in, hit);
+        let wo = frame.to_local(&-*ray_in.direction());
+        self.bsdf.eval(&wo, &frame.to_local(&direction.normalize()))
+    }
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        if direction.dot(&facing(&hit.normal, ray_in)) <= 0.0 {
+            return 0.0;
+        }
+        let frame = local_frame(&self.detail, ray_in, hit);
+        let wo = frame.to_local(&-*ray_in.direction());
+        self.bsdf.pdf(&wo, &frame.to_local(&direction.normalize()))
+    }
+}
+
+/// frosted glass, a GGX microfacet boundary that both reflects and refracts
+#[derive(Debug)]
+pub struct RoughDielectric {
+    albedo: Box<dyn Texture>,
+    pub refraction_index: f32,
+    distribution: Ggx,
+    detail: Option<SurfaceDetail>,
+}
+
+impl RoughDielectric {
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
+    }
+    pub fn new(refraction_index: f32, roughness: f32) -> Self {
+        RoughDielectric::textured(
+            Box::new(SolidColor::new(&Vec3::from_float(1.0))),
+            refraction_index,
+            roughness,
+        )
+    }
+    /// the texture tints everything passing through or reflecting off the surface
+    pub fn textured(albedo: Box<dyn Texture>, refraction_index: f32, roughness: f32) -> Self {
+        RoughDielectric {
+            albedo,
+            refraction_index,
+            distribution: Ggx::from_roughness(roughness),
+            detail: None,
+        }
+    }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+    pub fn distribution(&self) -> &Ggx {
+        &self.distribution
+    }
+
+    //the boundary as seen from the side ray_in arrives on, in its local frame, with wo
+    fn local(&self, ray_in: &Ray, hit: &Hit) -> (Onb, DielectricBsdf, Vec3) {
+        //normals face out of the glass
+        let entering = hit.normal.dot(ray_in.direction()) < 0.0;
+        let eta = if entering {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        };
+        let frame = local_frame(&self.detail, ray_in, hit);
+        let bsdf = DielectricBsdf {
+            distribution: self.distribution,
+            eta,
+        };
+        let wo = frame.to_local(&-*ray_in.direction());
+        (frame, bsdf, wo)
+    }
+}
+
+//whether a direction on the given side of the shading frame is on the same side of the surface
+fn same_side(local: &Vec3, direction: &Vec3, side: &Vec3) -> bool {
+    (local.z() > 0.0) == (direction.dot(side) > 0.0)
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let (frame, bsdf, wo) = self.local(ray_in, hit);
+        let wi = bsdf.sample(&wo, sampler)?;
+        let direction = frame.to_world(&wi);
+        //a tilted shading frame can send a reflection through the surface or a refraction back
+        let pdf = bsdf.pdf(&wo, &wi);
+        if !same_side(&wi, &direction, &facing(&hit.normal, ray_in)) || pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &direction, ray_in.time()),
+            attenuation: &hit.texture_value(&*self.albedo) * (bsdf.eval(&wo, &wi) / pdf),
+            pdf,
+            specular: false,
+        })
+    }
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let (frame, bsdf, wo) = self.local(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if !same_side(&wi, direction, &facing(&hit.normal, ray_in)) {
+            return Vec3::from_float(0.0);
+        }
+        &hit.texture_value(&*self.albedo) * bsdf.eval(&wo, &wi)
+    }
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        let (frame, bsdf, wo) = self.local(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if !same_side(&wi, direction, &facing(&hit.normal, ray_in)) {
+            return 0.0;
+        }
+        bsdf.pdf(&wo, &wi)
+    }
+}
+
+/// Disney's principled BSDF: one material that mixes a diffuse base with subsurface and sheen, a
+/// GGX specular lobe, a clearcoat and rough glass transmission, each set by a parameter in [0, 1]
+#[derive(Debug)]
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_gloss: f32,
+    transmission: f32,
+    refraction_index: f32,
+    subsurface: f32,
+    detail: Option<SurfaceDetail>,
+}
+
+impl Principled {
+    pub fn new(base_color: &Vec3) -> Self {
+        Principled::textured(Box::new(SolidColor::new(base_color)))
+    }
+    /// a rough dielectric with the usual 4% specular reflectance and no other lobes
+    pub fn textured(base_color: Box<dyn Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            refraction_index: 1.5,
+            subsurface: 0.0,
+            detail: None,
+        }
+    }
+    pub fn base_color(&self) -> &dyn Texture {
+        &*self.base_color
+    }
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+    /// specular scales the reflectance at normal incidence, 0.5 being 4%, and the tint colors
+    /// it by the base color
+    pub fn with_specular(mut self, specular: f32, tint: f32) -> Self {
+        self.specular = specular;
+        self.specular_tint = tint;
+        self
+    }
+    /// a soft grazing reflection for cloth, the tint colors it by the base color
+    pub fn with_sheen(mut self, sheen: f32, tint: f32) -> Self {
+        self.sheen = sheen;
+        self.sheen_tint = tint;
+        self
+    }
+    /// a second, colorless specular layer on top, gloss runs from satin to polished
+    pub fn with_clearcoat(mut self, clearcoat: f32, gloss: f32) -> Self {
+        self.clearcoat = clearcoat;
+        self.clearcoat_gloss = gloss;
+        self
+    }
+    /// how much of the non-metallic part is glass rather than diffuse, tinted by the base color
+    pub fn with_transmission(mut self, transmission: f32, refraction_index: f32) -> Self {
+        self.transmission = transmission;
+        self.refraction_index = refraction_index;
+        self
+    }
+    /// flattens the diffuse lobe the way light scattering under the surface does
+    pub fn with_subsurface(mut self, subsurface: f32) -> Self {
+        self.subsurface = subsurface;
+        self
+    }
+    pub fn with_detail(mut self, detail: SurfaceDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    //the lobes at this hit as seen from the side ray_in arrives on, the frame they are in and wo
+    fn local(&self, ray_in: &Ray, hit: &Hit) -> (Onb, PrincipledLobes, Vec3) {
+        let base = hit.texture_value(&*self.base_color);
+        let lum = luminance(&base);
+        let tint = if lum > 0.0 {
+            &base / lum
+        } else {
+            Vec3::from_float(1.0)
+        };
+        let white = Vec3::from_float(1.0);
+        let dielectric = 1.0 - self.metallic;
+        //normals face out of the material, and a ray inside it can only have come through glass
+        let entering = hit.normal.dot(ray_in.direction()) < 0.0;
+        let inside = self.transmission > 0.0 && !entering;
+        let eta = if entering {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        };
+        let weights = if inside {
+            [0.0, 0.0, 0.0, 1.0]
+        } else {
+            [
+                dielectric * (1.0 - self.transmission),
+                1.0 - dielectric * self.transmission,
+                0.25 * self.clearcoat,
+                dielectric * self.transmission,
+            ]
+        };
+        let lobes = PrincipledLobes {
+            specular0: lerp(
+                &(&lerp(&white, &tint, self.specular_tint) * (0.08 * self.specular)),
+                &base,
+                self.metallic,
+            ),
+            sheen: &lerp(&white, &tint, self.sheen_tint) * self.sheen,
+            base,
+            roughness: self.roughness,
+            subsurface: self.subsurface,
+            weights,
+            specular: Ggx::from_roughness(self.roughness),
+            clearcoat: Gtr1 {
+                alpha: 0.1 + (0.001 - 0.1) * self.clearcoat_gloss,
+            },
+            glass: DielectricBsdf {
+                distribution: Ggx::from_roughness(self.roughness),
+                eta,
+            },
+        };
+        let frame = local_frame(&self.detail, ray_in, hit);
+        let wo = frame.to_local(&-*ray_in.direction());
+        (frame, lobes, wo)
+    }
+}
+
+fn lerp(a: &Vec3, b: &Vec3, t: f32) -> Vec3 {
+    &(a * (1.0 - t)) + &(b * t)
+}
+
+//the clearcoat's masking is fixed, as in disney's model
+const CLEARCOAT_MASKING: Ggx = Ggx { alpha: 0.25 };
+
+//the principled lobes resolved at one hit, in a local frame with wo on the +z side
+struct PrincipledLobes {
+    base: Vec3,
+    sheen: Vec3,
+    specular0: Vec3,
+    roughness: f32,
+    subsurface: f32,
+    //the diffuse, specular, clearcoat and transmission lobes are scaled by these and picked for
+    //sampling in proportion to them
+    weights: [f32; 4],
+    specular: Ggx,
+    clearcoat: Gtr1,
+    glass: DielectricBsdf,
+}
+
+impl PrincipledLobes {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let [diffuse, specular, clearcoat, transmission] = self.weights;
+        let mut value = Vec3::from_float(0.0);
+        if transmission > 0.0 {
+            value += &(&self.base * (transmission * self.glass.eval(wo, wi)));
+        }
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return value;
+        }
+        let m = (wo + wi).normalize();
+        let cos_d = wi.dot(&m);
+        if diffuse > 0.0 {
+            //burley's retro-reflective diffuse and hanrahan-krueger's flattened subsurface
+            let (fl, fv) = (schlick_weight(wi.z()), schlick_weight(wo.z()));
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            let fss90 = self.roughness * cos_d * cos_d;
+            let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+            let ss = 1.25 * (fss * (1.0 / (wi.z() + wo.z()) - 0.5) + 0.5);
+            let shape = fd + (ss - fd) * self.subsurface;
+            let lobe =
+                &(&self.base * (shape / consts::PI)) + &(&self.sheen * schlick_weight(cos_d));
+            value += &(&lobe * (diffuse * wi.z()));
+        }
+        if specular > 0.0 {
+            let fresnel = &self.specular0
+                + &(&(&Vec3::from_float(1.0) - &self.specular0) * schlick_weight(wo.dot(&m)));
+            let dg = self.specular.d(&m) * self.specular.g(wo, wi);
+            value += &(&fresnel * (specular * dg / (4.0 * wo.z())));
+        }
+        if clearcoat > 0.0 {
+            let fresnel = 0.04 + 0.96 * schlick_weight(wo.dot(&m));
+            let dg = self.clearcoat.d(&m) * CLEARCOAT_MASKING.g(wo, wi);
+            value += clearcoat * fresnel * dg / (4.0 * wo.z());
+        }
+        value
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let total: f32 = self.weights.iter().sum();
+        if total <= 0.0 || wo.z() <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse, specular, clearcoat, transmission] = self.weights;
+        let mut pdf = transmission * self.glass.pdf(wo, wi);
+        if wi.z() > 0.0 {
+            let m = (wo + wi).normalize();
+            pdf += diffuse * wi.z() / consts::PI;
+            if wo.dot(&m) > 0.0 {
+                pdf += specular * self.specular.pdf_visible(wo, &m) / (4.0 * wo.dot(&m));
+                pdf += clearcoat * self.clearcoat.pdf(&m) / (4.0 * wo.dot(&m));
+            }
+        }
+        pdf / total
+    }
+
+    //picks a lobe in proportion to its weight and draws a direction from it
+    fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let total: f32 = self.weights.iter().sum();
+        let mut pick = sampler.gen() * total;
+        let lobe = self
+            .weights
+            .iter()
+            .position(|weight| {
+                pick -= weight;
+                pick < 0.0
+            })
+            .unwrap_or(3);
+        let wi = match lobe {
+            0 => {
+                let wi = &Vec3::new(0.0, 0.0, 1.0) + &rand_unit_vector(sampler);
+                if wi.squared_length() < 1e-12 {
+                    return None;
+                }
+                wi.normalize()
+            }
+            1 => mirror(wo, &self.specular.sample_visible(wo, sampler)),
+            2 => {
+                let m = self.clearcoat.sample(sampler);
+                if wo.dot(&m) <= 0.0 {
+                    return None;
+                }
+                mirror(wo, &m)
+            }
+            _ => return self.glass.sample(wo, sampler),
+        };
+        if wi.z() > 0.0 {
+            Some(wi)
+        } else {
+            None
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let (frame, lobes, wo) = self.local(ray_in, hit);
+        let wi = lobes.sample(&wo, sampler)?;
+        let direction = frame.to_world(&wi);
+        let pdf = lobes.pdf(&wo, &wi);
+        if !same_side(&wi, &direction, &facing(&hit.normal, ray_in)) || pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &direction, ray_in.time()),
+            attenuation: &lobes.eval(&wo, &wi) / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        let (frame, lobes, wo) = self.local(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if !same_side(&wi, direction, &facing(&hit.normal, ray_in)) {
+            return Vec3::from_float(0.0);
+        }
+        lobes.eval(&wo, &wi)
+    }
+    fn pdf(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> f32 {
+        let (frame, lobes, wo) = self.local(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if !same_side(&wi, direction, &facing(&hit.normal, ray_in)) {
+            return 0.0;
+        }
+        lobes.pdf(&wo, &wi)
+    }
+}
+
+/// the phase function of a participating medium, light leaves in any direction with equal chance
+#[derive(Debug)]
+pub struct Isotropic {
+    albedo: Box<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
+    }
+    pub fn new(albedo: &Vec3) -> Self {
+        Isotropic::textured(Box::new(SolidColor::new(albedo)))
+    }
+    pub fn textured(albedo: Box<dyn Texture>) -> Self {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &rand_unit_vector(sampler), ray_in.time()),
+            attenuation: hit.texture_value(&*self.albedo),
+            pdf: 1.0 / (4.0 * consts::PI),
+            specular: false,
+        })
+    }
+    fn eval(&self, _ray_in: &Ray, hit: &Hit, _direction: &Vec3) -> Vec3 {
+        &hit.texture_value(&*self.albedo) / (4.0 * consts::PI)
+    }
+    fn pdf(&self, _ray_in: &Ray, _hit: &Hit, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * consts::PI)
+    }
+}
+
+/// the phase function of a medium that scatters mostly forwards (g > 0) or backwards (g < 0)
+///
+/// g is the mean cosine between the incoming and scattered directions, 0 is isotropic
+#[derive(Debug)]
+pub struct HenyeyGreenstein {
+    albedo: Box<dyn Texture>,
+    pub g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn albedo(&self) -> &dyn Texture {
+        &*self.albedo
+    }
+    pub fn new(albedo: &Vec3, g: f32) -> Self {
+        HenyeyGreenstein::textured(Box::new(SolidColor::new(albedo)), g)
+    }
+    /// g is clamped to (-1, 1), at the ends the lobe collapses to a single direction
+    pub fn textured(albedo: Box<dyn Texture>, g: f32) -> Self {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+    /// density over the sphere of scattering by an angle with this cosine
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * consts::PI * denom * denom.sqrt())
+    }
+    /// cosine of the scattering angle drawn in proportion to the phase function
+    pub fn sample_cos_theta(&self, sampler: &mut Sampler) -> f32 {
+        let g = self.g;
+        let u = sampler.gen();
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * u;
         }
-        Ray::new(&hit.point, &refracted.unwrap())
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, hit: &Hit, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        let cos_theta = self.sample_cos_theta(sampler);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = sampler.gen_range(0.0, 2.0 * consts::PI);
+        //angles are measured from the incoming direction
+        let frame = Onb::from_normal(ray_in.direction());
+        let direction = frame.to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        //sampling the phase function exactly leaves the albedo as the weight
+        Some(ScatterRecord {
+            ray: Ray::at_time(&hit.point, &direction, ray_in.time()),
+            attenuation: hit.texture_value(&*self.albedo),
+            pdf: self.phase(cos_theta),
+            specular: false,
+        })
+    }
+    fn eval(&self, ray_in: &Ray, hit: &Hit, direction: &Vec3) -> Vec3 {
+        &hit.texture_value(&*self.albedo) * self.pdf(ray_in, hit, direction)
+    }
+    fn pdf(&self, ray_in: &Ray, _hit: &Hit, direction: &Vec3) -> f32 {
+        self.phase(ray_in.direction().dot(&direction.normalize()))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DiffuseLight {
+    emit: Vec3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: &Vec3) -> Self {
+        DiffuseLight { emit: *emit }
+    }
+    pub fn emit(&self) -> &Vec3 {
+        &self.emit
+    }
+}
+
+impl Material for DiffuseLight {
+    //lights absorb everything that reaches them
+    fn scatter(&self, _ray_in: &Ray, _hit: &Hit, _sampler: &mut Sampler) -> Option<ScatterRecord> {
+        None
+    }
+    fn emitted(&self, _hit: &Hit) -> Vec3 {
+        self.emit
+    }
+    fn emits(&self) -> bool {
+        self.emit != Vec3::from_float(0.0)
     }
 }
 
@@ -139,14 +921,14 @@ fn schlick(cosine: f32, index: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
-pub fn reflect(ray_in: &Vec3, normal: &Vec3, fuzz: f32) -> Vec3 {
+pub fn reflect(ray_in: &Vec3, normal: &Vec3, fuzz: f32, sampler: &mut Sampler) -> Vec3 {
     let projection_length = ray_in.dot(normal);
     let proj = normal * projection_length;
     let refl = ray_in - &(&proj * 2.0);
     if fuzz == 0.0 {
         return refl;
     }
-    &refl + &(&rand_in_unit_sphere() * fuzz)
+    &refl + &(&rand_in_unit_sphere(sampler) * fuzz)
 }
 
 pub fn refract(ray_in: &Vec3, normal: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
@@ -176,3 +958,255 @@ pub fn refract(ray_in: &Vec3, normal: &Vec3, ni_over_nt: f32) -> Option<Vec3> {
         Some(&pp + &b)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    //heights that rise along u
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f32, _v: f32, _point: &Vec3) -> Vec3 {
+            Vec3::from_float(u)
+        }
+    }
+
+    #[test]
+    fn test_surface_detail() {
+        let mut sampler = Sampler::from_seed(0);
+        let floor = Quad::new(
+            &Vec3::new(-1.0, 0.0, 1.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, -2.0),
+            Arc::new(Lambertian::new(&Vec3::from_float(0.5))),
+        );
+        let down = Ray::new(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(0.0, -1.0, 0.0));
+        let hit = floor
+            .intersect(&down, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        //the flat color of a normal map leaves the normal alone
+        let flat = SurfaceDetail::NormalMap(Box::new(SolidColor::new(&Vec3::new(0.5, 0.5, 1.0))));
+        assert!((&flat.normal(&hit) - &up).length() < 1e-6);
+        //one lying in the surface is lifted back above it
+        let sideways =
+            SurfaceDetail::NormalMap(Box::new(SolidColor::new(&Vec3::new(1.0, 0.5, 0.5))));
+        let normal = sideways.normal(&hit);
+        assert!((normal.dot(&up) - MIN_MAPPED_COSINE).abs() < 1e-4);
+        assert!(normal.x() > 0.99);
+
+        //a bump map leans the normal away from uphill
+        let bump = SurfaceDetail::BumpMap {
+            height: Box::new(Ramp),
+            strength: 0.5,
+        };
+        let expected = Vec3::new(-0.5, 1.0, 0.0).normalize();
+        assert!((&bump.normal(&hit) - &expected).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_scatter_record() {
+        let quad = |material: Arc<dyn Material>| {
+            Quad::new(
+                &Vec3::new(-1.0, 0.0, 1.0),
+                &Vec3::new(2.0, 0.0, 0.0),
+                &Vec3::new(0.0, 0.0, -2.0),
+                material,
+            )
+        };
+        let down = Ray::new(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(0.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(0, 0, 0);
+
+        let floor = quad(Arc::new(Lambertian::new(&Vec3::from_float(0.5))));
+        let hit = floor
+            .intersect(&down, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let trials = 20000;
+        let mut mean_cosine = 0.0;
+        for _ in 0..trials {
+            let scattered = hit.scatter(&down, &mut sampler).unwrap();
+            let cosine = scattered.ray.direction().y();
+            assert!(!scattered.specular);
+            assert!((scattered.pdf - cosine / consts::PI).abs() < 1e-5);
+            assert_eq!(scattered.attenuation, Vec3::from_float(0.5));
+            mean_cosine += cosine / trials as f32;
+        }
+        //directions drawn in proportion to the cosine have a mean cosine of 2/3
+        assert!((mean_cosine - 2.0 / 3.0).abs() < 0.01, "{}", mean_cosine);
+
+        let mirror = quad(Arc::new(Metal::new(&Vec3::from_float(0.9), 0.0)));
+        let hit = mirror
+            .intersect(&down, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let scattered = hit.scatter(&down, &mut sampler).unwrap();
+        assert!(scattered.specular && scattered.pdf == 0.0);
+        assert_eq!(*scattered.ray.direction(), Vec3::new(0.0, 1.0, 0.0));
+
+        let lamp = quad(Arc::new(DiffuseLight::new(&Vec3::from_float(4.0))));
+        let hit = lamp
+            .intersect(&down, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!(hit.scatter(&down, &mut sampler).is_none());
+        assert_eq!(hit.emitted(), Vec3::from_float(4.0));
+    }
+
+    #[test]
+    fn test_rough_materials() {
+        let quad = |material: Arc<dyn Material>| {
+            Quad::new(
+                &Vec3::new(-1.0, 0.0, 1.0),
+                &Vec3::new(2.0, 0.0, 0.0),
+                &Vec3::new(0.0, 0.0, -2.0),
+                material,
+            )
+        };
+        let slanted = Ray::new(&Vec3::new(-1.0, 1.0, 0.0), &Vec3::new(1.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(0, 0, 0);
+
+        let gold = quad(Arc::new(RoughConductor::named("gold", 0.4).unwrap()));
+        let hit = gold
+            .intersect(&slanted, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        for _ in 0..1000 {
+            if let Some(scattered) = hit.scatter(&slanted, &mut sampler) {
+                let direction = scattered.ray.direction();
+                assert!(direction.y() > 0.0 && !scattered.specular);
+                //scatter agrees with eval and pdf so light sampling can weigh its directions
+                let pdf = hit.pdf(&slanted, direction);
+                assert!(
+                    (pdf - scattered.pdf).abs() <= 1e-3 * pdf,
+                    "{} {}",
+                    pdf,
+                    scattered.pdf
+                );
+                let weight = &hit.eval(&slanted, direction) / pdf;
+                assert!((&weight - &scattered.attenuation).length() < 1e-3);
+                //gold reflects red more than blue
+                assert!(weight.x() > weight.z());
+            }
+        }
+        let below = Vec3::new(1.0, -1.0, 0.0);
+        assert_eq!(hit.pdf(&slanted, &below), 0.0);
+
+        let frosted = quad(Arc::new(RoughDielectric::new(1.5, 0.3)));
+        let hit = frosted
+            .intersect(&slanted, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let (mut reflected, mut transmitted) = (0, 0);
+        for _ in 0..1000 {
+            if let Some(scattered) = hit.scatter(&slanted, &mut sampler) {
+                let direction = scattered.ray.direction();
+                let pdf = hit.pdf(&slanted, direction);
+                assert!(
+                    (pdf - scattered.pdf).abs() <= 1e-3 * pdf,
+                    "{} {}",
+                    pdf,
+                    scattered.pdf
+                );
+                if direction.y() > 0.0 {
+                    reflected += 1;
+                } else {
+                    transmitted += 1;
+                }
+            }
+        }
+        assert!(transmitted > 4 * reflected, "{} {}", reflected, transmitted);
+        assert!(reflected > 0);
+    }
+
+    #[test]
+    fn test_principled() {
+        let quad = |material: Arc<dyn Material>| {
+            Quad::new(
+                &Vec3::new(-1.0, 0.0, 1.0),
+                &Vec3::new(2.0, 0.0, 0.0),
+                &Vec3::new(0.0, 0.0, -2.0),
+                material,
+            )
+        };
+        let slanted = Ray::new(&Vec3::new(-1.0, 1.0, 0.0), &Vec3::new(1.0, -1.0, 0.0));
+        let mut sampler = Sampler::new(0, 0, 0);
+        let red = Vec3::new(0.8, 0.1, 0.1);
+        let materials = [
+            Principled::new(&red),
+            Principled::new(&red)
+                .with_roughness(0.2)
+                .with_sheen(1.0, 0.5)
+                .with_clearcoat(1.0, 0.8)
+                .with_subsurface(0.5),
+            Principled::new(&red).with_metallic(1.0).with_roughness(0.3),
+            Principled::new(&red)
+                .with_transmission(1.0, 1.5)
+                .with_roughness(0.1),
+        ];
+        let mut means = vec![];
+        for material in materials {
+            let surface = quad(Arc::new(material));
+            let hit = surface
+                .intersect(&slanted, 0.001, f32::INFINITY, &mut sampler)
+                .unwrap();
+            let trials = 4000;
+            let (mut mean, mut below) = (Vec3::from_float(0.0), 0);
+            for _ in 0..trials {
+                let scattered = match hit.scatter(&slanted, &mut sampler) {
+                    Some(scattered) => scattered,
+                    None => continue,
+                };
+                let direction = scattered.ray.direction();
+                //every lobe's samples are weighed by the pdf of the whole mixture
+                let pdf = hit.pdf(&slanted, direction);
+                assert!(
+                    (pdf - scattered.pdf).abs() <= 1e-2 * pdf,
+                    "{} {}",
+                    pdf,
+                    scattered.pdf
+                );
+                let weight = &hit.eval(&slanted, direction) / pdf;
+                assert!((&weight - &scattered.attenuation).length() <= 1e-2 * weight.length());
+                mean += &(&scattered.attenuation / trials as f32);
+                if direction.y() < 0.0 {
+                    below += 1;
+                }
+            }
+            means.push((mean, below));
+        }
+        //only the glass sends light through, and the opaque ones are red
+        assert!(means[..3].iter().all(|(_, below)| *below == 0));
+        assert!(means[3].1 > 3200, "{}", means[3].1);
+        for (mean, _) in &means[..3] {
+            assert!(mean.x() > 2.0 * mean.y() && mean.x() < 1.2, "{:?}", mean);
+        }
+    }
+
+    #[test]
+    fn test_no_light_leaks() {
+        //a normal map tilted nearly into the surface and a ray coming in at a grazing angle
+        let tilted =
+            || SurfaceDetail::NormalMap(Box::new(SolidColor::new(&Vec3::new(0.0, 0.5, 0.55))));
+        let materials: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Lambertian::new(&Vec3::from_float(0.5)).with_detail(tilted())),
+            Arc::new(Metal::new(&Vec3::from_float(0.9), 0.3).with_detail(tilted())),
+        ];
+        let mut sampler = Sampler::new(0, 0, 0);
+        for material in materials {
+            let floor = Quad::new(
+                &Vec3::new(-1.0, 0.0, 1.0),
+                &Vec3::new(2.0, 0.0, 0.0),
+                &Vec3::new(0.0, 0.0, -2.0),
+                material,
+            );
+            let grazing = Ray::new(&Vec3::new(-1.0, 0.05, 0.0), &Vec3::new(1.0, -0.05, 0.0));
+            let hit = floor
+                .intersect(&grazing, 0.001, f32::INFINITY, &mut sampler)
+                .unwrap();
+            for _ in 0..1000 {
+                let scattered = hit.scatter(&grazing, &mut sampler).unwrap();
+                assert!(scattered.ray.direction().y() >= 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,688 @@
+//! 4x4 matrices and the affine transforms built from them.
+
+use super::aabb::*;
+use super::vec3::*;
+use std::ops::Mul;
+
+/// a row major 4x4 matrix acting on column vectors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4 {
+    m: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Self {
+        Matrix4 { m }
+    }
+    pub fn identity() -> Self {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn translation(offset: &Vec3) -> Self {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn scaling(factors: &Vec3) -> Self {
+        Matrix4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    /// counter-clockwise rotation by angle radians looking down axis towards the origin
+    pub fn rotation(axis: &Vec3, angle: f32) -> Self {
+        //rodrigues' rotation formula
+        let a = axis.normalize();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Matrix4::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn get(&self, row: usize, column: usize) -> f32 {
+        self.m[row][column]
+    }
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4::new(m)
+    }
+    /// None when the matrix is singular, e.g. a scale of zero along some axis
+    pub fn inverse(&self) -> Option<Self> {
+        //gauss-jordan elimination with partial pivoting on [m | identity]
+        let mut a = self.m;
+        let mut inv = Matrix4::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inv.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inv[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inv[row][j] -= factor * inv[column][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4::new(inv))
+    }
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x / w, y / w, z / w)
+        }
+    }
+    /// directions ignore the translation
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for &Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, other: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+/// an invertible affine transform, kept together with its inverse
+///
+/// the builder methods apply each step after the ones before it, so
+/// `Transform::identity().scale(..).rotate_y(..).translate(..)` scales first and translates last
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+    /// None when the matrix cannot be inverted
+    pub fn from_matrix(matrix: Matrix4) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+    /// self followed by other
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            matrix: &other.matrix * &self.matrix,
+            inverse: &self.inverse * &other.inverse,
+        }
+    }
+    pub fn translate(&self, offset: &Vec3) -> Transform {
+        self.then(&Transform {
+            matrix: Matrix4::translation(offset),
+            inverse: Matrix4::translation(&-*offset),
+        })
+    }
+    /// panics on a zero factor, which would flatten the object
+    pub fn scale(&self, factors: &Vec3) -> Transform {
+        assert!(
+            factors.x() != 0.0 && factors.y() != 0.0 && factors.z() != 0.0,
+            "scale factors must be non-zero"
+        );
+        self.then(&Transform {
+            matrix: Matrix4::scaling(factors),
+            inverse: Matrix4::scaling(&Vec3::new(
+                1.0 / factors.x(),
+                1.0 / factors.y(),
+                1.0 / factors.z(),
+            )),
+        })
+    }
+    /// angle in degrees, counter-clockwise looking down axis towards the origin
+    pub fn rotate(&self, axis: &Vec3, degrees: f32) -> Transform {
+        let rotation = Matrix4::rotation(axis, degrees.to_radians());
+        //rotations are orthogonal so the transpose is the inverse
+        self.then(&Transform {
+            matrix: rotation,
+            inverse: rotation.transpose(),
+        })
+    }
+    pub fn rotate_x(&self, degrees: f32) -> Transform {
+        self.rotate(&Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+    pub fn rotate_y(&self, degrees: f32) -> Transform {
+        self.rotate(&Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+    pub fn rotate_z(&self, degrees: f32) -> Transform {
+        self.rotate(&Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+    /// normals go through the inverse transpose so they stay perpendicular under non-uniform scale
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n).normalize()
+    }
+    /// the box around the eight transformed corners
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let (min, max) = (bbox.min(), bbox.max());
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x() } else { max.x() },
+                if i & 2 == 0 { min.y() } else { max.y() },
+                if i & 4 == 0 { min.z() } else { max.z() },
+            )
+        };
+        let first = self.point(&corner(0));
+        (1..8).fold(Aabb::new(&first, &first), |acc, i| {
+            acc.grow(&self.point(&corner(i)))
+        })
+    }
+}
+
+type Matrix3 = [[f32; 3]; 3];
+
+fn mul3(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose3(a: &Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = a[j][i];
+        }
+    }
+    m
+}
+
+fn det3(a: &Matrix3) -> f32 {
+    a[0][0] * (a[1][1] * a[2][2] - a[1][2] * a[2][1])
+        - a[0][1] * (a[1][0] * a[2][2] - a[1][2] * a[2][0])
+        + a[0][2] * (a[1][0] * a[2][1] - a[1][1] * a[2][0])
+}
+
+//the inverse transpose is the cofactor matrix over the determinant
+fn inverse_transpose3(a: &Matrix3) -> Matrix3 {
+    let det = det3(a);
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+            let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+            *value = (a[i1][j1] * a[i2][j2] - a[i1][j2] * a[i2][j1]) / det;
+        }
+    }
+    m
+}
+
+//a unit quaternion w + xi + yj + zk standing for a rotation
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Quaternion {
+    w: f32,
+    v: Vec3,
+}
+
+impl Quaternion {
+    fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    //shepperd's method, dividing by the largest of the four components for precision
+    fn from_matrix(r: &Matrix3) -> Self {
+        let trace = r[0][0] + r[1][1] + r[2][2];
+        let (w, x, y, z) = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            (
+                0.25 * s,
+                (r[2][1] - r[1][2]) / s,
+                (r[0][2] - r[2][0]) / s,
+                (r[1][0] - r[0][1]) / s,
+            )
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = 2.0 * (1.0 + r[0][0] - r[1][1] - r[2][2]).sqrt();
+            (
+                (r[2][1] - r[1][2]) / s,
+                0.25 * s,
+                (r[0][1] + r[1][0]) / s,
+                (r[0][2] + r[2][0]) / s,
+            )
+        } else if r[1][1] > r[2][2] {
+            let s = 2.0 * (1.0 + r[1][1] - r[0][0] - r[2][2]).sqrt();
+            (
+                (r[0][2] - r[2][0]) / s,
+                (r[0][1] + r[1][0]) / s,
+                0.25 * s,
+                (r[1][2] + r[2][1]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + r[2][2] - r[0][0] - r[1][1]).sqrt();
+            (
+                (r[1][0] - r[0][1]) / s,
+                (r[0][2] + r[2][0]) / s,
+                (r[1][2] + r[2][1]) / s,
+                0.25 * s,
+            )
+        };
+        Quaternion {
+            w,
+            v: Vec3::new(x, y, z),
+        }
+        .normalize()
+    }
+
+    fn normalize(&self) -> Self {
+        let length = self.dot(self).sqrt();
+        Quaternion {
+            w: self.w / length,
+            v: &self.v / length,
+        }
+    }
+
+    fn to_matrix(self) -> Matrix3 {
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    //the angle between the two rotations, the shorter way round
+    fn angle(&self, other: &Quaternion) -> f32 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    //turns at a constant rate from self at t = 0 to other at t = 1 the shorter way round
+    fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        //q and -q are the same rotation, pick the one on the near side
+        let (other, cos) = if self.dot(other) < 0.0 {
+            (
+                Quaternion {
+                    w: -other.w,
+                    v: -other.v,
+                },
+                -self.dot(other),
+            )
+        } else {
+            (*other, self.dot(other))
+        };
+        let (a, b) = if cos > 0.9995 {
+            //nearly parallel, where the sine below loses precision and a blend is as good
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quaternion {
+            w: a * self.w + b * other.w,
+            v: &(&self.v * a) + &(&other.v * b),
+        }
+        .normalize()
+    }
+}
+
+//an affine matrix split as translate * rotate * stretch, with the stretch symmetric
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Decomposed {
+    translation: Vec3,
+    rotation: Quaternion,
+    stretch: Matrix3,
+}
+
+impl Decomposed {
+    fn new(m: &Matrix4) -> Self {
+        let translation = Vec3::new(m.m[0][3], m.m[1][3], m.m[2][3]);
+        let mut a = [[0.0; 3]; 3];
+        for (i, row) in a.iter_mut().enumerate() {
+            row.copy_from_slice(&m.m[i][..3]);
+        }
+        //polar decomposition: averaging with the inverse transpose converges on the rotation
+        let mut r = a;
+        for _ in 0..100 {
+            let it = inverse_transpose3(&r);
+            let mut change: f32 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let next = 0.5 * (r[i][j] + it[i][j]);
+                    change = change.max((next - r[i][j]).abs());
+                    r[i][j] = next;
+                }
+            }
+            if change < 1e-7 {
+                break;
+            }
+        }
+        //a mirroring transform leaves an improper rotation, move the reflection into the stretch
+        if det3(&r) < 0.0 {
+            r = r.map(|row| row.map(|value| -value));
+        }
+        let stretch = mul3(&transpose3(&r), &a);
+        Decomposed {
+            translation,
+            rotation: Quaternion::from_matrix(&r),
+            stretch,
+        }
+    }
+
+    fn interpolate(&self, other: &Decomposed, t: f32) -> Matrix4 {
+        let rotation = self.rotation.slerp(&other.rotation, t).to_matrix();
+        let mut blend = [[0.0; 3]; 3];
+        for (i, row) in blend.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (1.0 - t) * self.stretch[i][j] + t * other.stretch[i][j];
+            }
+        }
+        let a = mul3(&rotation, &blend);
+        let translation = &(&self.translation * (1.0 - t)) + &(&other.translation * t);
+        Matrix4::new([
+            [a[0][0], a[0][1], a[0][2], translation.x()],
+            [a[1][0], a[1][1], a[1][2], translation.y()],
+            [a[2][0], a[2][1], a[2][2], translation.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+//the number of instants the motion bound is taken at
+const MOTION_BOUND_STEPS: usize = 32;
+
+/// a transform that changes from start at time0 to end at time1
+///
+/// each end is split into a translation, a rotation and a stretch. translation and stretch are
+/// blended linearly and the rotation turns at a constant rate the shorter way round, so an object
+/// keeps its size while it spins
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    time0: f32,
+    time1: f32,
+    decomposed: [Decomposed; 2],
+}
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform, time0: f32, time1: f32) -> Self {
+        AnimatedTransform {
+            start,
+            end,
+            time0,
+            time1,
+            decomposed: [Decomposed::new(&start.matrix), Decomposed::new(&end.matrix)],
+        }
+    }
+    pub fn start(&self) -> &Transform {
+        &self.start
+    }
+    pub fn end(&self) -> &Transform {
+        &self.end
+    }
+    /// held at the end positions outside [time0, time1], None at the instant a motion from a
+    /// mirrored end to an unmirrored one flattens the object
+    pub fn at(&self, time: f32) -> Option<Transform> {
+        let t = if self.time1 > self.time0 {
+            ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        if t == 0.0 {
+            return Some(self.start);
+        }
+        if t == 1.0 {
+            return Some(self.end);
+        }
+        let [start, end] = &self.decomposed;
+        Transform::from_matrix(start.interpolate(end, t))
+    }
+    /// the boxes at evenly spaced instants, grown by how far a point can bow out from the chord
+    /// between two of them as it turns
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let [start, end] = &self.decomposed;
+        let mut bound = self.start.bounding_box(bbox);
+        for step in 1..=MOTION_BOUND_STEPS {
+            let t = step as f32 / MOTION_BOUND_STEPS as f32;
+            if let Some(transform) = Transform::from_matrix(start.interpolate(end, t)) {
+                bound = bound.union(&transform.bounding_box(bbox));
+            }
+        }
+        let turn = start.rotation.angle(&end.rotation) / MOTION_BOUND_STEPS as f32;
+        if turn == 0.0 {
+            return bound;
+        }
+        //the farthest a corner of the stretched box gets from the center of rotation
+        let mut reach: f32 = 0.0;
+        for stretch in [start.stretch, end.stretch] {
+            for corner in 0..8 {
+                let pick = |axis: usize| {
+                    if corner >> axis & 1 == 0 {
+                        bbox.min()[axis]
+                    } else {
+                        bbox.max()[axis]
+                    }
+                };
+                let p = [pick(0), pick(1), pick(2)];
+                let stretched = Vec3::new(
+                    (0..3).map(|k| stretch[0][k] * p[k]).sum(),
+                    (0..3).map(|k| stretch[1][k] * p[k]).sum(),
+                    (0..3).map(|k| stretch[2][k] * p[k]).sum(),
+                );
+                reach = reach.max(stretched.length());
+            }
+        }
+        let bow = Vec3::from_float(reach * (1.0 - (0.5 * turn).cos()));
+        Aabb::new(&(bound.min() - &bow), &(bound.max() + &bow))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn test_inverse() {
+        let transform = Transform::identity()
+            .scale(&Vec3::new(2.0, 0.5, -3.0))
+            .rotate(&Vec3::new(1.0, 1.0, 0.0), 33.0)
+            .translate(&Vec3::new(1.0, -2.0, 5.0));
+        let general = transform.matrix().inverse().unwrap();
+        let product = &general * transform.matrix();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product.get(i, j) - expected).abs() < 1e-5);
+            }
+        }
+        let p = Vec3::new(0.3, -0.7, 2.0);
+        assert!(close(&transform.inverse().point(&transform.point(&p)), &p));
+        assert!(Matrix4::scaling(&Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn test_order_and_normals() {
+        let transform = Transform::identity()
+            .rotate_z(90.0)
+            .translate(&Vec3::new(1.0, 0.0, 0.0));
+        //rotated first, so x ends up along y before moving
+        let p = transform.point(&Vec3::new(1.0, 0.0, 0.0));
+        assert!(close(&p, &Vec3::new(1.0, 1.0, 0.0)));
+        assert!(close(
+            &transform.vector(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0)
+        ));
+        //squashing a 45 degree slope along y tilts its normal towards y
+        let squash = Transform::identity().scale(&Vec3::new(1.0, 0.5, 1.0));
+        let normal = squash.normal(&Vec3::new(1.0, 1.0, 0.0).normalize());
+        assert!(close(&normal, &Vec3::new(1.0, 2.0, 0.0).normalize()));
+    }
+
+    #[test]
+    fn test_animated() {
+        let start = Transform::identity().translate(&Vec3::new(-1.0, 0.0, 0.0));
+        let end = Transform::identity()
+            .scale(&Vec3::from_float(3.0))
+            .translate(&Vec3::new(1.0, 2.0, 0.0));
+        let motion = AnimatedTransform::new(start, end, 0.0, 2.0);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        assert!(close(
+            &motion.at(-1.0).unwrap().point(&origin),
+            &Vec3::new(-1.0, 0.0, 0.0)
+        ));
+        assert!(close(
+            &motion.at(1.0).unwrap().point(&origin),
+            &Vec3::new(0.0, 1.0, 0.0)
+        ));
+        assert!(close(
+            &motion.at(5.0).unwrap().point(&origin),
+            &Vec3::new(1.0, 2.0, 0.0)
+        ));
+        let halfway = motion.at(1.0).unwrap();
+        let p = Vec3::new(1.0, 1.0, 1.0);
+        assert!(close(&halfway.point(&p), &Vec3::new(2.0, 3.0, 2.0)));
+        assert!(close(&halfway.inverse().point(&halfway.point(&p)), &p));
+        //the unit box starts shifted along -x and ends three times larger around (1, 2, 0)
+        let unit = Aabb::new(&Vec3::from_float(0.0), &Vec3::from_float(1.0));
+        let bbox = motion.bounding_box(&unit);
+        assert!(close(bbox.min(), &Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(close(bbox.max(), &Vec3::new(4.0, 5.0, 3.0)));
+    }
+
+    #[test]
+    fn test_animated_rotation() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let scaled = Transform::identity().scale(&Vec3::new(2.0, 1.0, 1.0));
+        //a half turn blurs through the quarter instead of collapsing or snapping to an end
+        for (degrees, halfway) in [
+            (90.0, Vec3::new(2.0, 0.0, -2.0).normalize()),
+            (180.0, Vec3::new(0.0, 0.0, 1.0)),
+        ] {
+            let motion = AnimatedTransform::new(scaled, scaled.rotate_y(degrees), 0.0, 1.0);
+            let p = motion.at(0.5).unwrap().point(&x);
+            assert!((p.length() - 2.0).abs() < 1e-5, "{:?}", p);
+            let mid = &halfway * 2.0;
+            assert!(close(&p, &mid) || close(&p, &-mid), "{:?}", p);
+            //the bound holds every position along the way
+            let unit = Aabb::new(&Vec3::from_float(-1.0), &Vec3::from_float(1.0));
+            let bbox = motion.bounding_box(&unit);
+            for step in 0..=100 {
+                let transform = motion.at(step as f32 / 100.0).unwrap();
+                let moved = transform.bounding_box(&unit);
+                for axis in 0..3 {
+                    assert!(bbox.min()[axis] <= moved.min()[axis] + 1e-4);
+                    assert!(bbox.max()[axis] >= moved.max()[axis] - 1e-4);
+                }
+            }
+        }
+        //the decomposition takes a mirror and a shear apart and puts them back together
+        let general = Transform::identity()
+            .scale(&Vec3::new(-1.0, 2.0, 0.5))
+            .rotate(&Vec3::new(1.0, 2.0, 3.0), 40.0)
+            .scale(&Vec3::new(1.0, 3.0, 1.0))
+            .translate(&Vec3::new(1.0, -1.0, 2.0));
+        let still = AnimatedTransform::new(general, general, 0.0, 1.0);
+        let p = Vec3::new(0.3, -0.7, 1.1);
+        assert!(close(&still.at(0.5).unwrap().point(&p), &general.point(&p)));
+    }
+}
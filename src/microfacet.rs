@@ -0,0 +1,503 @@
+//! GGX microfacet BSDFs for rough metals and glass.
+//!
+//! Everything here works in a local shading frame with the surface normal along +z, and with
+//! wo, the direction back along the incoming ray, on the +z side. Both directions point away
+//! from the surface. `eval` gives the BSDF times the cosine of wi, so `eval / pdf` is the weight
+//! of a sampled direction.
+
+use super::sampler::*;
+use super::vec3::*;
+use std::f32::consts;
+
+/// the Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith masking-shadowing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    pub alpha: f32,
+}
+
+impl Ggx {
+    /// alpha is the square of the perceptual roughness in [0, 1], kept away from zero where the
+    /// distribution becomes a spike
+    pub fn from_roughness(roughness: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    /// density of microfacet normals m, normalized so the projected area is one
+    pub fn d(&self, m: &Vec3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = m.z() * m.z() * (a2 - 1.0) + 1.0;
+        a2 / (consts::PI * t * t)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) * 0.5
+    }
+
+    /// the fraction of microfacets facing w that are visible from it
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// the height correlated fraction visible from both wo and wi
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// a microfacet normal drawn in proportion to how much of it wo sees, Heitz 2018
+    pub fn sample_visible(&self, wo: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        //stretch wo so the distribution becomes a hemisphere
+        let v = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).normalize();
+        let length2 = v.x() * v.x() + v.y() * v.y();
+        let t1 = if length2 > 0.0 {
+            &Vec3::new(-v.y(), v.x(), 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(&t1);
+        //a point on the disk, squeezed onto the part of the hemisphere seen from v
+        let r = sampler.gen().sqrt();
+        let phi = 2.0 * consts::PI * sampler.gen();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let n = &(&(&t1 * p1) + &(&t2 * p2)) + &(&v * p3);
+        //and unstretch the normal
+        Vec3::new(self.alpha * n.x(), self.alpha * n.y(), n.z().max(1e-6)).normalize()
+    }
+
+    /// density over microfacet normals of `sample_visible`
+    pub fn pdf_visible(&self, wo: &Vec3, m: &Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z()
+    }
+}
+
+/// reflectance of a boundary into a medium eta times as dense, for light arriving at an angle
+/// with this cosine to the normal on the outside, negative cosines arrive from the inside
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    //total internal reflection
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// reflectance of a metal with the complex index of refraction eta + ik per color channel
+pub fn fresnel_conductor(cos_i: f32, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let channel = |eta: f32, k: f32| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t1 = a2b2 + cos2;
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Vec3::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+/// the complex index of refraction of a few common metals at red, green and blue wavelengths
+pub fn conductor_ior(name: &str) -> Option<(Vec3, Vec3)> {
+    Some(match name {
+        "gold" => (
+            Vec3::new(0.143, 0.374, 1.442),
+            Vec3::new(3.983, 2.385, 1.603),
+        ),
+        "silver" => (
+            Vec3::new(0.155, 0.117, 0.138),
+            Vec3::new(4.828, 3.122, 2.147),
+        ),
+        "copper" => (
+            Vec3::new(0.200, 0.924, 1.102),
+            Vec3::new(3.912, 2.452, 2.142),
+        ),
+        "aluminium" => (
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+        ),
+        _ => return None,
+    })
+}
+
+/// the generalized Trowbridge-Reitz distribution with exponent 1 that Disney uses for clearcoat,
+/// with a longer tail than GGX
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Gtr1 {
+    pub alpha: f32,
+}
+
+impl Gtr1 {
+    /// density of microfacet normals m, normalized so the projected area is one
+    pub fn d(&self, m: &Vec3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = 1.0 + (a2 - 1.0) * m.z() * m.z();
+        (a2 - 1.0) / (consts::PI * a2.ln() * t)
+    }
+
+    /// a microfacet normal drawn in proportion to d times its cosine
+    pub fn sample(&self, sampler: &mut Sampler) -> Vec3 {
+        let a2 = self.alpha * self.alpha;
+        let cos2 = (1.0 - a2.powf(1.0 - sampler.gen())) / (1.0 - a2);
+        let cos_theta = cos2.clamp(0.0, 1.0).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * consts::PI * sampler.gen();
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    pub fn pdf(&self, m: &Vec3) -> f32 {
+        self.d(m) * m.z()
+    }
+}
+
+/// the (1 - cosine)^5 falloff of Schlick's fresnel approximation
+pub fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+/// the names `conductor_ior` knows
+pub const CONDUCTORS: [&str; 4] = ["gold", "silver", "copper", "aluminium"];
+
+/// wo mirrored about the microfacet normal m
+pub fn mirror(wo: &Vec3, m: &Vec3) -> Vec3 {
+    &(m * (2.0 * wo.dot(m))) - wo
+}
+
+/// a rough metal surface
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ConductorBsdf {
+    pub distribution: Ggx,
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+impl ConductorBsdf {
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::from_float(0.0);
+        }
+        let m = (wo + wi).normalize();
+        let fresnel = fresnel_conductor(wo.dot(&m), &self.eta, &self.k);
+        //the cosine of wi cancels against the one in the denominator of the bsdf
+        let scale = self.distribution.d(&m) * self.distribution.g(wo, wi) / (4.0 * wo.z());
+        &fresnel * scale
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let m = (wo + wi).normalize();
+        //the jacobian of reflecting about m
+        self.distribution.pdf_visible(wo, &m) / (4.0 * wo.dot(&m))
+    }
+
+    /// None when the reflection off the drawn microfacet heads into the surface
+    pub fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let m = self.distribution.sample_visible(wo, sampler);
+        let wi = mirror(wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+/// a rough boundary between two dielectrics, eta is the index of refraction on the far side of
+/// the surface from wo over the one on its side
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DielectricBsdf {
+    pub distribution: Ggx,
+    pub eta: f32,
+}
+
+impl DielectricBsdf {
+    //the microfacet normal that takes wo to wi, on the +z side, and whether it reflects
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, bool)> {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+        let reflected = wi.z() > 0.0;
+        let m = if reflected {
+            wo + wi
+        } else {
+            wo + &(wi * self.eta)
+        };
+        if m.squared_length() == 0.0 {
+            return None;
+        }
+        let m = m.normalize();
+        let m = if m.z() < 0.0 { -m } else { m };
+        //wo must see the front of the microfacet and a refraction must leave through its back
+        if wo.dot(&m) <= 0.0 || (!reflected && wi.dot(&m) >= 0.0) {
+            return None;
+        }
+        Some((m, reflected))
+    }
+
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let (m, reflected) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo.dot(&m), self.eta);
+        let dg = self.distribution.d(&m) * self.distribution.g(wo, wi);
+        if reflected {
+            fresnel * dg / (4.0 * wo.z())
+        } else {
+            //walter et al. 2007 carrying radiance, which crossing the boundary is divided by the
+            //square of eta as its cone of directions widens or narrows
+            let denom = wo.dot(&m) + self.eta * wi.dot(&m);
+            (1.0 - fresnel) * dg * wi.dot(&m).abs() * wo.dot(&m) / (wo.z() * denom * denom)
+        }
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let (m, reflected) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo.dot(&m), self.eta);
+        let pdf_m = self.distribution.pdf_visible(wo, &m);
+        if reflected {
+            fresnel * pdf_m / (4.0 * wo.dot(&m))
+        } else {
+            let denom = wo.dot(&m) + self.eta * wi.dot(&m);
+            (1.0 - fresnel) * pdf_m * self.eta * self.eta * wi.dot(&m).abs() / (denom * denom)
+        }
+    }
+
+    /// reflects or refracts off a drawn microfacet, choosing by its fresnel term
+    pub fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let m = self.distribution.sample_visible(wo, sampler);
+        let cos_i = wo.dot(&m);
+        let fresnel = fresnel_dielectric(cos_i, self.eta);
+        if sampler.gen() < fresnel {
+            let wi = mirror(wo, &m);
+            return if wi.z() > 0.0 { Some(wi) } else { None };
+        }
+        let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (self.eta * self.eta);
+        let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+        let wi = &(&m * (cos_i / self.eta - cos_t)) - &(wo / self.eta);
+        if wi.z() < 0.0 {
+            Some(wi.normalize())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //directions spread evenly over the sphere by a fibonacci lattice, each standing for 4pi/n
+    fn sphere_directions(n: usize) -> Vec<Vec3> {
+        //in f64 since the angle grows far beyond where f32 keeps it precise
+        let golden = std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+        (0..n)
+            .map(|i| {
+                let z = 1.0 - (i as f64 + 0.5) * 2.0 / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = (golden * i as f64) % (2.0 * std::f64::consts::PI);
+                Vec3::new((r * phi.cos()) as f32, (r * phi.sin()) as f32, z as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ggx() {
+        let n = 200_000;
+        let directions = sphere_directions(n);
+        let solid_angle = 4.0 * consts::PI / n as f32;
+        for roughness in [0.3, 0.6, 1.0] {
+            let ggx = Ggx::from_roughness(roughness);
+            //the projected microfacet area is the macro surface
+            let area: f32 = directions.iter().map(|m| ggx.d(m) * m.z().max(0.0)).sum();
+            assert!(
+                (area * solid_angle - 1.0).abs() < 0.01,
+                "{}",
+                area * solid_angle
+            );
+            //visible normals are a distribution for any wo
+            let wo = Vec3::new(0.6, 0.0, 0.8);
+            let total: f32 = directions.iter().map(|m| ggx.pdf_visible(&wo, m)).sum();
+            assert!(
+                (total * solid_angle - 1.0).abs() < 0.01,
+                "{}",
+                total * solid_angle
+            );
+            let mut sampler = Sampler::from_seed(1);
+            for _ in 0..100 {
+                let m = ggx.sample_visible(&wo, &mut sampler);
+                assert!(m.z() > 0.0 && wo.dot(&m) > -1e-4);
+                assert!((m.length() - 1.0).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gtr1() {
+        let n = 200_000;
+        let directions = sphere_directions(n);
+        let solid_angle = 4.0 * consts::PI / n as f32;
+        for alpha in [0.1, 0.25] {
+            let gtr1 = Gtr1 { alpha };
+            let area: f32 = directions.iter().map(|m| gtr1.pdf(m)).sum();
+            assert!(
+                (area * solid_angle - 1.0).abs() < 0.01,
+                "{}",
+                area * solid_angle
+            );
+            //samples follow the pdf, so their mean cosine matches its integral
+            let expected: f32 = directions.iter().map(|m| gtr1.pdf(m) * m.z()).sum();
+            let mut sampler = Sampler::from_seed(2);
+            let trials = 20000;
+            let mut mean = 0.0;
+            for _ in 0..trials {
+                let m = gtr1.sample(&mut sampler);
+                assert!(m.z() >= 0.0 && (m.length() - 1.0).abs() < 1e-4);
+                mean += m.z() / trials as f32;
+            }
+            assert!((mean - expected * solid_angle).abs() < 0.01, "{}", mean);
+        }
+    }
+
+    #[test]
+    fn test_fresnel() {
+        //(n - 1)^2 / (n + 1)^2 at normal incidence, from either side
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert!((fresnel_dielectric(-1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert_eq!(fresnel_dielectric(-0.2, 1.5), 1.0);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-6);
+        //a conductor without absorption is a dielectric
+        for cos in [0.1, 0.5, 0.9] {
+            let conductor = fresnel_conductor(cos, &Vec3::from_float(1.5), &Vec3::from_float(0.0));
+            assert!((conductor.x() - fresnel_dielectric(cos, 1.5)).abs() < 1e-5);
+        }
+        let (eta, k) = conductor_ior("gold").unwrap();
+        let gold = fresnel_conductor(1.0, &eta, &k);
+        assert!(gold.x() > gold.y() && gold.y() > gold.z());
+        assert!(conductor_ior("tin").is_none());
+    }
+
+    #[test]
+    fn test_conductor() {
+        let n = 200_000;
+        let directions = sphere_directions(n);
+        let solid_angle = 4.0 * consts::PI / n as f32;
+        let (eta, k) = conductor_ior("silver").unwrap();
+        let wo = Vec3::new(0.5, 0.1, 0.8).normalize();
+        let mut sampler = Sampler::from_seed(2);
+        //rougher surfaces lose more
+        let mut previous = 1.01;
+        for roughness in [0.2, 0.5, 0.9] {
+            let bsdf = ConductorBsdf {
+                distribution: Ggx::from_roughness(roughness),
+                eta,
+                k,
+            };
+            //reflections off microfacets that head below the horizon are lost
+            let total: f32 = directions.iter().map(|wi| bsdf.pdf(&wo, wi)).sum();
+            let total = total * solid_angle;
+            assert!(total < 1.01 && total > 0.5 && total < previous, "{}", total);
+            previous = total;
+            //the mean sample weight is the albedo, which conserves energy
+            let trials = 20000;
+            let mut albedo = Vec3::from_float(0.0);
+            for _ in 0..trials {
+                if let Some(wi) = bsdf.sample(&wo, &mut sampler) {
+                    let pdf = bsdf.pdf(&wo, &wi);
+                    assert!(pdf > 0.0);
+                    albedo += &(&bsdf.eval(&wo, &wi) / pdf);
+                }
+            }
+            albedo /= trials as f32;
+            let integral: Vec3 = directions
+                .iter()
+                .fold(Vec3::from_float(0.0), |sum, wi| &sum + &bsdf.eval(&wo, wi));
+            let integral = &integral * solid_angle;
+            assert!(albedo.x() <= total, "{:?}", albedo);
+            assert!(
+                (&albedo - &integral).length() < 0.03,
+                "{:?} {:?}",
+                albedo,
+                integral
+            );
+        }
+    }
+
+    #[test]
+    fn test_dielectric() {
+        let n = 400_000;
+        let directions = sphere_directions(n);
+        let solid_angle = 4.0 * consts::PI / n as f32;
+        let mut sampler = Sampler::from_seed(3);
+        //from outside into glass and from inside out of it
+        for eta in [1.5, 1.0 / 1.5] {
+            let bsdf = DielectricBsdf {
+                distribution: Ggx::from_roughness(0.5),
+                eta,
+            };
+            let wo = Vec3::new(0.3, 0.0, 0.9).normalize();
+            let total: f32 = directions.iter().map(|wi| bsdf.pdf(&wo, wi)).sum();
+            let total = total * solid_angle;
+            assert!(total < 1.01 && total > 0.9, "{}", total);
+            let trials = 20000;
+            let (mut reflected, mut weight) = (0, 0.0);
+            for _ in 0..trials {
+                if let Some(wi) = bsdf.sample(&wo, &mut sampler) {
+                    let pdf = bsdf.pdf(&wo, &wi);
+                    assert!(pdf > 0.0, "{:?}", wi);
+                    let weight_i = bsdf.eval(&wo, &wi) / pdf;
+                    if wi.z() > 0.0 {
+                        reflected += 1;
+                        weight += weight_i;
+                    } else {
+                        //undo the change in radiance across the boundary to count energy
+                        weight += weight_i * eta * eta;
+                    }
+                }
+            }
+            //glass loses nothing but what masking and shadowing take
+            let weight = weight / trials as f32;
+            assert!(weight <= 1.01 && weight > 0.85, "{}", weight);
+            assert!(reflected > 0 && reflected < trials / 2);
+        }
+    }
+}
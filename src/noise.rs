@@ -0,0 +1,201 @@
+//! Coherent noise for procedural textures, seeded through the renderer's sampler.
+
+use super::sampler::*;
+use super::vec3::*;
+
+const POINT_COUNT: usize = 256;
+
+/// how values are blended between the lattice points around a lookup
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Smoothing {
+    /// plain trilinear weights, the lattice shows as creases
+    Linear,
+    /// weights eased with the hermite cubic 3t^2 - 2t^3 so the noise is smooth across cells
+    Hermite,
+}
+
+/// Ken Perlin's gradient noise, with values roughly in [-1, 1]
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+    smoothing: Smoothing,
+}
+
+impl Perlin {
+    /// the same sampler state always builds the same noise
+    pub fn new(sampler: &mut Sampler) -> Self {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                //uniform on the sphere
+                let z = sampler.gen_range(-1.0, 1.0);
+                let phi = sampler.gen_range(0.0, 2.0 * std::f32::consts::PI);
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                Vec3::new(r * phi.cos(), r * phi.sin(), z)
+            })
+            .collect();
+        Perlin {
+            gradients,
+            perm_x: permutation(sampler),
+            perm_y: permutation(sampler),
+            perm_z: permutation(sampler),
+            smoothing: Smoothing::Hermite,
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn noise(&self, p: &Vec3) -> f32 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let fraction = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let cell = [floor[0] as i64, floor[1] as i64, floor[2] as i64];
+        let weights = fraction.map(|t| match self.smoothing {
+            Smoothing::Linear => t,
+            Smoothing::Hermite => t * t * (3.0 - 2.0 * t),
+        });
+        let wrap = |i: i64| (i & (POINT_COUNT as i64 - 1)) as usize;
+        let mut total = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let gradient = &self.gradients[self.perm_x[wrap(cell[0] + offset[0])]
+                ^ self.perm_y[wrap(cell[1] + offset[1])]
+                ^ self.perm_z[wrap(cell[2] + offset[2])]];
+            //the gradient is dotted with the vector from its corner to the point
+            let to_point = Vec3::new(
+                fraction[0] - offset[0] as f32,
+                fraction[1] - offset[1] as f32,
+                fraction[2] - offset[2] as f32,
+            );
+            let mut weight = 1.0;
+            for axis in 0..3 {
+                weight *= if offset[axis] == 1 {
+                    weights[axis]
+                } else {
+                    1.0 - weights[axis]
+                };
+            }
+            total += weight * gradient.dot(&to_point);
+        }
+        total
+    }
+
+    /// depth octaves of noise, each at twice the frequency and half the weight of the last
+    pub fn turbulence(&self, p: &Vec3, depth: u32) -> f32 {
+        let mut total = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            total += weight * self.noise(&point);
+            weight *= 0.5;
+            point = &point * 2.0;
+        }
+        total.abs()
+    }
+}
+
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    //fisher-yates shuffle of 0..POINT_COUNT
+    let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let j = ((sampler.gen() * (i + 1) as f32) as usize).min(i);
+        perm.swap(i, j);
+    }
+    perm
+}
+
+/// Worley's cellular noise: the distance to the nearest of a set of points scattered one per
+/// unit cell
+#[derive(Debug, Copy, Clone)]
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(sampler: &mut Sampler) -> Self {
+        Worley {
+            seed: sampler.gen_u64(),
+        }
+    }
+
+    /// the feature point of the cell, anywhere inside it
+    fn feature(&self, cell: [i64; 3]) -> Vec3 {
+        let hash = cell.iter().fold(self.seed, |hash, &i| mix(hash, i as u64));
+        //21 bits for each coordinate
+        let coordinate = |shift: u32| ((hash >> shift) & 0x1F_FFFF) as f32 / (1 << 21) as f32;
+        Vec3::new(
+            cell[0] as f32 + coordinate(0),
+            cell[1] as f32 + coordinate(21),
+            cell[2] as f32 + coordinate(42),
+        )
+    }
+
+    /// distance to the nearest feature point, mostly below 1
+    pub fn distance(&self, p: &Vec3) -> f32 {
+        let cell = [
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        ];
+        let mut nearest = f32::INFINITY;
+        //the nearest point is always in one of the 27 cells around the point
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let feature = self.feature([cell[0] + dx, cell[1] + dy, cell[2] + dz]);
+                    nearest = nearest.min((&feature - p).length());
+                }
+            }
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_perlin() {
+        let a = Perlin::new(&mut Sampler::from_seed(1));
+        let b = Perlin::new(&mut Sampler::from_seed(1));
+        let c = Perlin::new(&mut Sampler::from_seed(2));
+        let p = Vec3::new(1.3, -2.7, 0.4);
+        assert_eq!(a.noise(&p), b.noise(&p));
+        assert_ne!(a.noise(&p), c.noise(&p));
+        //gradient noise vanishes on the lattice
+        assert_eq!(a.noise(&Vec3::new(3.0, -1.0, 7.0)), 0.0);
+        //and is continuous across cell faces
+        let below = a.noise(&Vec3::new(0.5, 0.999_99, 0.5));
+        let above = a.noise(&Vec3::new(0.5, 1.000_01, 0.5));
+        assert!((below - above).abs() < 1e-3);
+        let values: Vec<f32> = (0..1000)
+            .map(|i| a.noise(&Vec3::new(i as f32 * 0.137, i as f32 * 0.071, 0.5)))
+            .collect();
+        assert!(values.iter().all(|v| v.abs() <= 1.0));
+        assert!(values.iter().any(|v| *v > 0.2) && values.iter().any(|v| *v < -0.2));
+        let linear = a.clone().with_smoothing(Smoothing::Linear);
+        assert_ne!(linear.noise(&p), a.noise(&p));
+        assert!(a.turbulence(&p, 7) >= 0.0);
+    }
+
+    #[test]
+    fn test_worley() {
+        let worley = Worley::new(&mut Sampler::from_seed(5));
+        let same = Worley::new(&mut Sampler::from_seed(5));
+        let p = Vec3::new(0.3, 4.2, -1.7);
+        assert_eq!(worley.distance(&p), same.distance(&p));
+        //a feature point is at distance zero from itself
+        let feature = worley.feature([2, -3, 0]);
+        assert!(worley.distance(&feature) < 1e-6);
+        //no point is further than the diagonal of a cell from the feature point of its own cell
+        for i in 0..200 {
+            let q = Vec3::new(i as f32 * 0.31, i as f32 * -0.17, i as f32 * 0.05);
+            assert!(worley.distance(&q) <= 3.0f32.sqrt());
+        }
+    }
+}
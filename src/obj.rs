@@ -0,0 +1,655 @@
+//! Wavefront OBJ and MTL loading.
+
+use super::bvh::*;
+use super::materials::*;
+use super::shapes::*;
+use super::texture::*;
+use super::vec3::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub type Mesh = Bvh<Triangle>;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        file: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ObjVertex {
+    pub position: usize,
+    pub texcoord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ObjFace {
+    pub vertices: [ObjVertex; 3],
+    /// index into the obj's materials, None uses the default material
+    pub material: Option<usize>,
+    pub group: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+    pub refraction_index: Option<f32>,
+    pub dissolve: f32,
+    pub illum: Option<u32>,
+    /// the map_Kd image, resolved relative to the mtl file
+    pub diffuse_map: Option<PathBuf>,
+    /// the norm image, a tangent space normal map
+    pub normal_map: Option<PathBuf>,
+    /// the bump or map_Bump height image, with the -bm multiplier as its strength
+    pub bump_map: Option<(PathBuf, f32)>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        MtlMaterial {
+            name: name.to_string(),
+            diffuse: Vec3::from_float(0.8),
+            specular: Vec3::from_float(0.0),
+            shininess: 0.0,
+            refraction_index: None,
+            dissolve: 1.0,
+            illum: None,
+            diffuse_map: None,
+            normal_map: None,
+            bump_map: None,
+        }
+    }
+
+    /// map the phong style description onto the closest of the crate's materials, loading the
+    /// diffuse, normal and bump maps it names
+    pub fn to_material(&self) -> Result<Arc<dyn Material>, ObjError> {
+        let transparent =
+            self.dissolve < 1.0 || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        let reflective = matches!(self.illum, Some(3) | Some(5) | Some(8));
+        let specular = max_component(&self.specular);
+        //texture maps tile across the surface as in other obj viewers
+        let diffuse: Box<dyn Texture> = match &self.diffuse_map {
+            Some(path) => Box::new(
+                ImageTexture::load(path)
+                    .map_err(|error| ObjError::Io {
+                        file: path.clone(),
+                        error,
+                    })?
+                    .with_filter(Filter::Bilinear)
+                    .with_wrap(Wrap::Repeat),
+            ),
+            None => Box::new(SolidColor::new(&self.diffuse)),
+        };
+        //normal and bump maps hold data rather than colors so they skip the sRGB decode
+        let load_linear = |path: &PathBuf| -> Result<Box<dyn Texture>, ObjError> {
+            Ok(Box::new(
+                ImageTexture::load_linear(path)
+                    .map_err(|error| ObjError::Io {
+                        file: path.clone(),
+                        error,
+                    })?
+                    .with_filter(Filter::Bilinear)
+                    .with_wrap(Wrap::Repeat),
+            ))
+        };
+        //a normal map is the more precise of the two when a material has both
+        let detail = match (&self.normal_map, &self.bump_map) {
+            (Some(path), _) => Some(SurfaceDetail::NormalMap(load_linear(path)?)),
+            (None, Some((path, strength))) => Some(SurfaceDetail::BumpMap {
+                height: load_linear(path)?,
+                strength: *strength,
+            }),
+            (None, None) => None,
+        };
+        Ok(if transparent {
+            let mut material = Dielectric::new(
+                self.refraction_index.filter(|&n| n > 1.0).unwrap_or(1.5),
+                0.0,
+            );
+            if let Some(detail) = detail {
+                material = material.with_detail(detail);
+            }
+            Arc::new(material)
+        } else if reflective || specular > max_component(&self.diffuse) {
+            //a common conversion from the phong exponent to a roughness in [0, 1]
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            let albedo: Box<dyn Texture> = if specular > 0.0 {
+                Box::new(SolidColor::new(&self.specular))
+            } else {
+                diffuse
+            };
+            let mut material = Metal::textured(albedo, fuzz.min(1.0));
+            if let Some(detail) = detail {
+                material = material.with_detail(detail);
+            }
+            Arc::new(material)
+        } else {
+            let mut material = Lambertian::textured(diffuse);
+            if let Some(detail) = detail {
+                material = material.with_detail(detail);
+            }
+            Arc::new(material)
+        })
+    }
+}
+
+fn max_component(v: &Vec3) -> f32 {
+    v.x().max(v.y()).max(v.z())
+}
+
+/// the contents of an OBJ file with its materials
+#[derive(Debug)]
+pub struct Obj {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub texcoords: Vec<(f32, f32)>,
+    pub faces: Vec<ObjFace>,
+    pub groups: Vec<String>,
+    pub mtl_materials: Vec<MtlMaterial>,
+    materials: Vec<Arc<dyn Material>>,
+    default_material: Arc<dyn Material>,
+}
+
+impl Obj {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Obj, ObjError> {
+        let path = path.as_ref();
+        let source = read(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Obj::parse(path, &source, |library| {
+            let mtl_path = directory.join(library);
+            let mtl_source = read(&mtl_path)?;
+            parse_mtl(&mtl_path, &mtl_source)
+        })
+    }
+
+    /// file is only used to label errors, material libraries are resolved through load_mtl
+    pub fn parse<F>(file: &Path, source: &str, mut load_mtl: F) -> Result<Obj, ObjError>
+    where
+        F: FnMut(&str) -> Result<Vec<MtlMaterial>, ObjError>,
+    {
+        let mut obj = Obj {
+            positions: vec![],
+            normals: vec![],
+            texcoords: vec![],
+            faces: vec![],
+            groups: vec!["default".to_string()],
+            mtl_materials: vec![],
+            materials: vec![],
+            default_material: Arc::new(Lambertian::new(&Vec3::from_float(0.8))),
+        };
+        let mut material = None;
+        let mut group = 0;
+        for (number, line) in source.lines().enumerate() {
+            let text = strip_comment(line).trim();
+            let mut parser = LineParser {
+                file,
+                line: number + 1,
+                text,
+                tokens: text.split_whitespace(),
+            };
+            let keyword = match parser.tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            match keyword {
+                "v" => {
+                    let position = parser.vec3()?;
+                    obj.positions.push(position);
+                }
+                "vn" => {
+                    let normal = parser.vec3()?;
+                    obj.normals.push(normal);
+                }
+                "vt" => {
+                    let u = parser.float()?;
+                    //v and w are optional, w is not needed for 2d textures
+                    let v = parser.optional_float()?.unwrap_or(0.0);
+                    obj.texcoords.push((u, v));
+                }
+                "f" => {
+                    let mut vertices = vec![];
+                    for token in &mut parser.tokens {
+                        vertices.push(obj.vertex(file, number + 1, token)?);
+                    }
+                    if vertices.len() < 3 {
+                        return Err(parser.error("a face needs at least three vertices"));
+                    }
+                    //triangulate polygons as a fan around their first vertex
+                    for i in 1..vertices.len() - 1 {
+                        obj.faces.push(ObjFace {
+                            vertices: [vertices[0], vertices[i], vertices[i + 1]],
+                            material,
+                            group,
+                        });
+                    }
+                }
+                "g" | "o" => {
+                    let name = parser.rest().unwrap_or("default");
+                    group = match obj.groups.iter().position(|g| g == name) {
+                        Some(index) => index,
+                        None => {
+                            obj.groups.push(name.to_string());
+                            obj.groups.len() - 1
+                        }
+                    };
+                }
+                "mtllib" => {
+                    let library = parser
+                        .rest()
+                        .ok_or_else(|| parser.error("mtllib needs a file name"))?;
+                    for mtl in load_mtl(library)? {
+                        //later definitions replace earlier ones with the same name
+                        match obj.mtl_materials.iter().position(|m| m.name == mtl.name) {
+                            Some(index) => obj.mtl_materials[index] = mtl,
+                            None => obj.mtl_materials.push(mtl),
+                        }
+                    }
+                }
+                "usemtl" => {
+                    let name = parser
+                        .rest()
+                        .ok_or_else(|| parser.error("usemtl needs a material name"))?;
+                    material = Some(
+                        obj.mtl_materials
+                            .iter()
+                            .position(|m| m.name == name)
+                            .ok_or_else(|| parser.error(&format!("unknown material '{}'", name)))?,
+                    );
+                }
+                //smoothing groups, free-form geometry, lines and points are not rendered
+                _ => {}
+            }
+        }
+        obj.materials = obj
+            .mtl_materials
+            .iter()
+            .map(|m| m.to_material())
+            .collect::<Result<_, _>>()?;
+        Ok(obj)
+    }
+
+    fn vertex(&self, file: &Path, line: usize, token: &str) -> Result<ObjVertex, ObjError> {
+        let error = |message: String| ObjError::Parse {
+            file: file.to_path_buf(),
+            line,
+            message,
+        };
+        //indices are one based, negative indices count back from the latest element
+        let resolve = |index: &str, len: usize, kind: &str| -> Result<usize, ObjError> {
+            let value: i64 = index
+                .parse()
+                .map_err(|_| error(format!("invalid {} index '{}'", kind, index)))?;
+            let resolved = if value > 0 {
+                value - 1
+            } else {
+                len as i64 + value
+            };
+            if value == 0 || resolved < 0 || resolved >= len as i64 {
+                return Err(error(format!("{} index {} out of range", kind, value)));
+            }
+            Ok(resolved as usize)
+        };
+        let mut parts = token.split('/');
+        let position = resolve(parts.next().unwrap_or(""), self.positions.len(), "position")?;
+        let texcoord = match parts.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve(index, self.texcoords.len(), "texcoord")?),
+        };
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve(index, self.normals.len(), "normal")?),
+        };
+        if parts.next().is_some() {
+            return Err(error(format!("malformed face vertex '{}'", token)));
+        }
+        Ok(ObjVertex {
+            position,
+            texcoord,
+            normal,
+        })
+    }
+
+    pub fn materials(&self) -> &[Arc<dyn Material>] {
+        &self.materials
+    }
+
+    /// the triangles share the obj's materials so they outlive it
+    pub fn triangle(&self, face: &ObjFace) -> Triangle {
+        let material = match face.material {
+            Some(index) => self.materials[index].clone(),
+            None => self.default_material.clone(),
+        };
+        let vertices = [
+            self.positions[face.vertices[0].position],
+            self.positions[face.vertices[1].position],
+            self.positions[face.vertices[2].position],
+        ];
+        let normals: Option<Vec<Vec3>> = face
+            .vertices
+            .iter()
+            .map(|vertex| vertex.normal.map(|i| self.normals[i]))
+            .collect();
+        let triangle = match normals {
+            //degenerate normals cannot be interpolated so fall back to the face normal
+            Some(n) if n.iter().all(|normal| normal.squared_length() > 0.0) => {
+                Triangle::smooth(vertices, [n[0], n[1], n[2]], material)
+            }
+            _ => Triangle::new(vertices, material),
+        };
+        let texcoords: Option<Vec<(f32, f32)>> = face
+            .vertices
+            .iter()
+            .map(|vertex| vertex.texcoord.map(|i| self.texcoords[i]))
+            .collect();
+        match texcoords {
+            Some(t) => triangle.with_texcoords([t[0], t[1], t[2]]),
+            None => triangle,
+        }
+    }
+
+    pub fn triangles(&self) -> Vec<Triangle> {
+        self.faces.iter().map(|face| self.triangle(face)).collect()
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        Bvh::new(self.triangles())
+    }
+
+    pub fn group_mesh(&self, name: &str) -> Option<Mesh> {
+        let group = self.groups.iter().position(|g| g == name)?;
+        Some(Bvh::new(
+            self.faces
+                .iter()
+                .filter(|face| face.group == group)
+                .map(|face| self.triangle(face))
+                .collect(),
+        ))
+    }
+}
+
+pub fn parse_mtl(file: &Path, source: &str) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut materials: Vec<MtlMaterial> = vec![];
+    for (number, line) in source.lines().enumerate() {
+        let text = strip_comment(line).trim();
+        let mut parser = LineParser {
+            file,
+            line: number + 1,
+            text,
+            tokens: text.split_whitespace(),
+        };
+        let keyword = match parser.tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = parser
+                .rest()
+                .ok_or_else(|| parser.error("newmtl needs a material name"))?;
+            materials.push(MtlMaterial::new(name));
+            continue;
+        }
+        let current = match materials.last_mut() {
+            Some(current) => current,
+            None => return Err(parser.error(&format!("'{}' before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => current.diffuse = parser.vec3()?,
+            "Ks" => current.specular = parser.vec3()?,
+            "Ns" => current.shininess = parser.float()?,
+            "Ni" => current.refraction_index = Some(parser.float()?),
+            "d" => current.dissolve = parser.float()?,
+            "Tr" => current.dissolve = 1.0 - parser.float()?,
+            "illum" => {
+                let illum = parser
+                    .tokens
+                    .next()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| parser.error("illum needs an integer model"))?;
+                current.illum = Some(illum);
+            }
+            "map_Kd" => {
+                //options such as -s come before the file name, which is taken to be the last token
+                let name = parser
+                    .tokens
+                    .clone()
+                    .last()
+                    .ok_or_else(|| parser.error("map_Kd needs a file name"))?;
+                let directory = file.parent().unwrap_or_else(|| Path::new(""));
+                current.diffuse_map = Some(directory.join(name));
+            }
+            "norm" => {
+                let name = parser
+                    .tokens
+                    .clone()
+                    .last()
+                    .ok_or_else(|| parser.error("norm needs a file name"))?;
+                let directory = file.parent().unwrap_or_else(|| Path::new(""));
+                current.normal_map = Some(directory.join(name));
+            }
+            "bump" | "map_Bump" | "map_bump" => {
+                let tokens: Vec<&str> = parser.tokens.clone().collect();
+                let name = tokens
+                    .last()
+                    .ok_or_else(|| parser.error("bump needs a file name"))?;
+                //-bm scales the heights, other options are ignored
+                let strength = match tokens.iter().position(|&token| token == "-bm") {
+                    Some(i) => tokens
+                        .get(i + 1)
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| parser.error("-bm needs a number"))?,
+                    None => 1.0,
+                };
+                let directory = file.parent().unwrap_or_else(|| Path::new(""));
+                current.bump_map = Some((directory.join(name), strength));
+            }
+            //other texture maps, ambient and emissive terms have no equivalent yet
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        file: path.to_path_buf(),
+        error,
+    })
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+struct LineParser<'a> {
+    file: &'a Path,
+    line: usize,
+    text: &'a str,
+    tokens: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> LineParser<'a> {
+    fn error(&self, message: &str) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_path_buf(),
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn optional_float(&mut self) -> Result<Option<f32>, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(&format!("invalid number '{}'", token))),
+            None => Ok(None),
+        }
+    }
+
+    fn float(&mut self) -> Result<f32, ObjError> {
+        self.optional_float()?
+            .ok_or_else(|| self.error("missing number"))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    //names may contain spaces so take everything after the keyword
+    fn rest(&self) -> Option<&'a str> {
+        self.text
+            .split_once(char::is_whitespace)
+            .map(|(_, rest)| rest.trim())
+            .filter(|rest| !rest.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MTL: &str = "
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl glass
+Ni 1.45
+d 0.1
+";
+
+    const OBJ: &str = "
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+vt 0 0
+g quad
+usemtl red
+f 1//1 2//1 3//1 4//1 # a quad becomes two triangles
+g glass panel
+usemtl glass
+f -4 -3 -2
+";
+
+    fn parse(source: &str) -> Result<Obj, ObjError> {
+        Obj::parse(Path::new("test.obj"), source, |library| {
+            assert_eq!(library, "scene.mtl");
+            parse_mtl(Path::new("scene.mtl"), MTL)
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let obj = parse(OBJ).unwrap();
+        assert_eq!(obj.positions.len(), 4);
+        assert_eq!(obj.faces.len(), 3);
+        assert_eq!(obj.groups, vec!["default", "quad", "glass panel"]);
+        assert_eq!(obj.faces[1].vertices[2].position, 3);
+        assert_eq!(obj.faces[1].vertices[2].normal, Some(0));
+        assert_eq!(obj.faces[2].vertices[0].position, 0);
+        assert_eq!(obj.faces[2].material, Some(1));
+        assert_eq!(obj.mtl_materials[1].refraction_index, Some(1.45));
+        assert_eq!(obj.mesh().len(), 3);
+        assert_eq!(obj.group_mesh("quad").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        let error = parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test.obj:3: position index 3 out of range"
+        );
+        let error = parse("v 0 0 zero\n").unwrap_err();
+        assert_eq!(error.to_string(), "test.obj:1: invalid number 'zero'");
+        let error = parse("mtllib scene.mtl\nusemtl blue\n").unwrap_err();
+        assert_eq!(error.to_string(), "test.obj:2: unknown material 'blue'");
+        let error = parse_mtl(Path::new("scene.mtl"), "Kd 1 1 1\n").unwrap_err();
+        assert_eq!(error.to_string(), "scene.mtl:1: 'Kd' before any newmtl");
+    }
+
+    #[test]
+    fn test_normal_and_bump_maps() {
+        let source = "newmtl brick\nnorm textures/brick_n.png\nbump -bm 0.5 textures/brick_h.png\n\
+                      newmtl stone\nmap_Bump textures/stone.png\n";
+        let materials = parse_mtl(Path::new("models/scene.mtl"), source).unwrap();
+        let brick = &materials[0];
+        assert_eq!(
+            brick.normal_map.as_deref(),
+            Some(Path::new("models/textures/brick_n.png"))
+        );
+        assert_eq!(
+            brick.bump_map,
+            Some((PathBuf::from("models/textures/brick_h.png"), 0.5))
+        );
+        assert_eq!(
+            materials[1].bump_map,
+            Some((PathBuf::from("models/textures/stone.png"), 1.0))
+        );
+        //the normal map is preferred when both are given
+        let error = brick.to_material().unwrap_err().to_string();
+        assert!(
+            error.starts_with("models/textures/brick_n.png: "),
+            "{}",
+            error
+        );
+        let error = parse_mtl(Path::new("a.mtl"), "newmtl a\nbump -bm x.png\n").unwrap_err();
+        assert!(
+            error.to_string().contains("-bm needs a number"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_diffuse_map() {
+        let source = "newmtl wood\nKd 1 1 1\nmap_Kd -s 2 2 1 textures/wood.png\n";
+        let materials = parse_mtl(Path::new("models/scene.mtl"), source).unwrap();
+        let map = materials[0].diffuse_map.as_ref().unwrap();
+        assert_eq!(map, Path::new("models/textures/wood.png"));
+        //the map is read when the material is made, a missing one names its file
+        let error = materials[0].to_material().unwrap_err().to_string();
+        assert!(error.starts_with("models/textures/wood.png: "), "{}", error);
+    }
+}
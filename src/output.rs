@@ -0,0 +1,193 @@
+//! The linear framebuffer and the image formats it can be saved as.
+
+use super::tonemap::*;
+use super::vec3::*;
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageError, Rgb};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum OutputError {
+    UnsupportedFormat(PathBuf),
+    Io { file: PathBuf, error: io::Error },
+    Encode { file: PathBuf, error: ImageError },
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::UnsupportedFormat(file) => write!(
+                f,
+                "{}: unsupported image format, expected .png, .ppm or .hdr",
+                file.display()
+            ),
+            OutputError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            OutputError::Encode { file, error } => write!(f, "{}: {}", file.display(), error),
+        }
+    }
+}
+
+impl Error for OutputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OutputError::UnsupportedFormat(_) => None,
+            OutputError::Io { error, .. } => Some(error),
+            OutputError::Encode { error, .. } => Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    /// 8 bit, gamma encoded
+    Png,
+    /// 8 bit binary P6, gamma encoded
+    Ppm,
+    /// radiance rgbe, linear high dynamic range
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+/// linear radiance per pixel, rows from top to bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3::from_float(0.0); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Vec3 {
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: &Vec3) {
+        self.pixels[y * self.width + x] = *color;
+    }
+
+    /// tone mapped and encoded bytes for 8 bit formats
+    pub fn to_rgb8(&self, tonemap: &Tonemap) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| tonemap.quantize(color))
+            .collect()
+    }
+
+    /// the format is picked from the extension of path, hdr output stays linear and ignores tonemap
+    pub fn save<P: AsRef<Path>>(&self, path: P, tonemap: &Tonemap) -> Result<(), OutputError> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)
+            .ok_or_else(|| OutputError::UnsupportedFormat(path.to_path_buf()))?;
+        let io_error = |error| OutputError::Io {
+            file: path.to_path_buf(),
+            error,
+        };
+        let encode_error = |error| OutputError::Encode {
+            file: path.to_path_buf(),
+            error,
+        };
+        let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        match format {
+            ImageFormat::Png => self.write_png(&mut writer, tonemap).map_err(encode_error)?,
+            ImageFormat::Ppm => self.write_ppm(&mut writer, tonemap).map_err(io_error)?,
+            ImageFormat::Hdr => self.write_hdr(&mut writer).map_err(encode_error)?,
+        }
+        writer.flush().map_err(io_error)
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W, tonemap: &Tonemap) -> Result<(), ImageError> {
+        PngEncoder::new(writer).encode(
+            &self.to_rgb8(tonemap),
+            self.width as u32,
+            self.height as u32,
+            ColorType::Rgb8,
+        )
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut writer: W, tonemap: &Tonemap) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.to_rgb8(tonemap))
+    }
+
+    pub fn write_hdr<W: Write>(&self, writer: W) -> Result<(), ImageError> {
+        let pixels: Vec<Rgb<f32>> = self
+            .pixels
+            .iter()
+            .map(|color| Rgb([color.r().max(0.0), color.g().max(0.0), color.b().max(0.0)]))
+            .collect();
+        HdrEncoder::new(writer).encode(&pixels, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ImageFormat::from_path("out.PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("a/b.ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_path("render.hdr"), Some(ImageFormat::Hdr));
+        assert_eq!(ImageFormat::from_path("render.jpg"), None);
+        assert_eq!(ImageFormat::from_path("render"), None);
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set(0, 0, &Vec3::new(1.0, 0.5, 0.0));
+        framebuffer.set(1, 0, &Vec3::new(2.0, -1.0, 0.2));
+        let mut data = vec![];
+        framebuffer
+            .write_ppm(&mut data, &Tonemap::default())
+            .unwrap();
+        let header = b"P6\n2 1\n255\n";
+        assert_eq!(&data[..header.len()], header);
+        //0.5 and 0.2 land on the familiar sRGB values, out of range values are clamped
+        assert_eq!(&data[header.len()..], &[255, 188, 0, 255, 0, 124]);
+        let mut data = vec![];
+        let tonemap = Tonemap {
+            operator: ToneOperator::Reinhard,
+            ..Tonemap::default()
+        };
+        framebuffer.write_ppm(&mut data, &tonemap).unwrap();
+        //highlights are no longer clipped
+        assert!(data[header.len()] < 255);
+    }
+}
@@ -1,16 +1,25 @@
+//! Rays with an origin, a unit direction and a time.
+
 use super::vec3::*;
 
+/// a half line from origin along a unit direction, cast at an instant within the shutter
 #[derive(Debug)]
 pub struct Ray {
     origin: Box<Vec3>,
     direction: Box<Vec3>,
+    time: f32,
 }
 
 impl Ray {
+    /// a ray at time zero
     pub fn new(origin: &Vec3, direction: &Vec3) -> Ray {
+        Ray::at_time(origin, direction, 0.0)
+    }
+    pub fn at_time(origin: &Vec3, direction: &Vec3, time: f32) -> Ray {
         Ray {
-            origin: Box::new(origin.clone()),
+            origin: Box::new(*origin),
             direction: Box::new(direction.normalize()),
+            time,
         }
     }
     pub fn parameterization(&self, t: f32) -> Vec3 {
@@ -22,13 +31,18 @@ impl Ray {
     pub fn origin(&self) -> &Vec3 {
         &self.origin
     }
+    /// moving objects are placed where they are at this time
+    pub fn time(&self) -> f32 {
+        self.time
+    }
     pub fn from_spherical(origin: &Vec3, phi: f32, theta: f32) -> Self {
         //origin: source of ray
         //phi φ in [0, pi] indicates a deviation in radians from the +z axis
         //theta in [0, 2pi] indicates a deviation from the +x axis in the x-y plane
         Ray {
-            origin: Box::new(origin.clone()),
+            origin: Box::new(*origin),
             direction: Box::new(Vec3::from_spherical(1.0, phi, theta)),
+            time: 0.0,
         }
     }
 }
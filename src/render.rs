@@ -0,0 +1,334 @@
+//! The path tracing integrator and the tiled, multithreaded render loop.
+//!
+//! At every vertex that does not scatter into a single direction the integrator also samples a
+//! point on a light and casts a shadow ray to it. Light found that way and light a scattered ray
+//! reaches by chance are weighed against each other with the power heuristic, so each comes from
+//! whichever strategy finds it with less noise. Without lights this is a plain random walk.
+
+use super::camera::*;
+use super::lights::*;
+use super::output::*;
+use super::ray::*;
+use super::sampler::*;
+use super::scene::*;
+use super::shapes::*;
+use super::vec3::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// tiles are the unit of work handed to threads
+pub const TILE_SIZE: usize = 32;
+
+/// radiance arriving along ray, following at most max_hits bounces
+pub fn color(
+    ray: &Ray,
+    world: &dyn Intersect,
+    lights: &Lights,
+    background: &Background,
+    max_hits: u32,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    trace(ray, world, lights, background, max_hits, None, sampler)
+}
+
+//scatter_pdf is the density with which the last vertex scattered into ray when it also sampled
+//the lights, None after a specular bounce or from the camera
+fn trace(
+    ray: &Ray,
+    world: &dyn Intersect,
+    lights: &Lights,
+    background: &Background,
+    max_hits: u32,
+    scatter_pdf: Option<f32>,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    if max_hits == 0 {
+        return Vec3::from_float(0.0);
+    }
+    let hit = match world.intersect(ray, 0.001, f32::INFINITY, sampler) {
+        Some(hit) => hit,
+        None => return background.color(ray),
+    };
+    let mut emitted = hit.emitted();
+    if let Some(scatter_pdf) = scatter_pdf {
+        if emitted != Vec3::from_float(0.0) {
+            emitted *= power_heuristic(scatter_pdf, lights.pdf(ray, &hit, sampler));
+        }
+    }
+    //absorbing materials end the path so there is nothing more to trace
+    let scattered = match hit.scatter(ray, sampler) {
+        Some(scattered) if scattered.attenuation != Vec3::from_float(0.0) => scattered,
+        _ => return emitted,
+    };
+    let (direct, scatter_pdf) = if scattered.specular || lights.is_empty() {
+        (Vec3::from_float(0.0), None)
+    } else {
+        (
+            direct_light(ray, &hit, world, lights, sampler),
+            Some(scattered.pdf),
+        )
+    };
+    let incoming = trace(
+        &scattered.ray,
+        world,
+        lights,
+        background,
+        max_hits - 1,
+        scatter_pdf,
+        sampler,
+    );
+    &(&emitted + &direct) + &(&scattered.attenuation * &incoming)
+}
+
+//light reaching hit straight from a point sampled on one of the lights
+fn direct_light(
+    ray: &Ray,
+    hit: &Hit,
+    world: &dyn Intersect,
+    lights: &Lights,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    let black = Vec3::from_float(0.0);
+    let point = match lights.sample_point(&hit.point, sampler) {
+        Some(point) => point,
+        None => return black,
+    };
+    let to_light = &point - &hit.point;
+    let distance = to_light.length();
+    if distance <= 0.001 {
+        return black;
+    }
+    let shadow = Ray::at_time(&hit.point, &to_light, ray.time());
+    let f = hit.eval(ray, shadow.direction());
+    if f == black {
+        return black;
+    }
+    //the light is visible when the shadow ray meets nothing before the sampled point
+    let light_hit = match world.intersect(&shadow, 0.001, f32::INFINITY, sampler) {
+        Some(light_hit) if (light_hit.distance - distance).abs() <= 1e-3 * distance.max(1.0) => {
+            light_hit
+        }
+        _ => return black,
+    };
+    let light_pdf = lights.pdf(&shadow, &light_hit, sampler);
+    if light_pdf <= 0.0 {
+        return black;
+    }
+    let weight = power_heuristic(light_pdf, hit.pdf(ray, shadow.direction()));
+    &(&f * &light_hit.emitted()) * (weight / light_pdf)
+}
+
+/// the weight of a sample drawn with density pdf against another strategy with density other
+pub fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a.is_infinite() {
+        return 1.0;
+    }
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Tile {
+    //pixel bounds with rows counted from the top of the image
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+fn tiles(width: usize, height: usize) -> Vec<Tile> {
+    let mut tiles = vec![];
+    for y0 in (0..height).step_by(TILE_SIZE) {
+        for x0 in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + TILE_SIZE).min(width),
+                y1: (y0 + TILE_SIZE).min(height),
+            });
+        }
+    }
+    tiles
+}
+
+fn render_tile(
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: &dyn Intersect,
+    lights: &Lights,
+    tile: &Tile,
+) -> Vec<Vec3> {
+    let nx = settings.width as usize;
+    let ny = settings.height as usize;
+    let ns = settings.samples;
+    let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+    for row in tile.y0..tile.y1 {
+        let j = ny - 1 - row;
+        for i in tile.x0..tile.x1 {
+            let mut col = Vec3::new(0.0, 0.0, 0.0);
+            let pixel = (row * nx + i) as u64;
+            for sample in 0..ns {
+                let mut sampler = Sampler::new(settings.seed, pixel, sample as u64);
+                let rr = sampler.gen();
+                let u = ((i as f32) + rr) / (nx as f32);
+                let v = ((j as f32) + rr) / (ny as f32);
+                let r = camera.get_ray(u, v, &mut sampler);
+                col += &color(
+                    &r,
+                    world,
+                    lights,
+                    &settings.background,
+                    settings.max_depth,
+                    &mut sampler,
+                );
+            }
+            col /= ns as f32;
+            pixels.push(col);
+        }
+    }
+    pixels
+}
+
+/// renders the world as seen by camera into a framebuffer of linear radiance, sampling the lights
+/// for direct lighting
+pub fn render(
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: &dyn Intersect,
+    lights: &Lights,
+) -> Framebuffer {
+    let width = settings.width as usize;
+    let height = settings.height as usize;
+    let tiles = tiles(width, height);
+    let threads = settings
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, tiles.len().max(1));
+    let next = AtomicUsize::new(0);
+    let framebuffer = Mutex::new(Framebuffer::new(width, height));
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                //threads pull tiles until none are left so faster threads take on more of the work
+                while let Some(tile) = tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let colors = render_tile(settings, camera, world, lights, tile);
+                    let mut framebuffer = framebuffer.lock().unwrap();
+                    let mut colors = colors.iter();
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            framebuffer.set(x, y, colors.next().unwrap());
+                        }
+                    }
+                }
+            });
+        }
+    });
+    framebuffer.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::materials::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_independent_of_thread_count() {
+        let diffuse = Arc::new(Lambertian::new(&Vec3::new(0.8, 0.3, 0.3)));
+        let glass = Arc::new(Dielectric::new(1.5, 0.0));
+        let world = Intersectables::new(vec![
+            Arc::new(Sphere::new(&Vec3::new(-0.5, 0.0, -1.0), 0.5, diffuse)),
+            Arc::new(Sphere::new(&Vec3::new(0.5, 0.0, -1.0), 0.5, glass)),
+        ]);
+        let camera = Camera::new(
+            &Vec3::new(0.0, 0.0, 1.0),
+            &Vec3::new(0.0, 0.0, -1.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            2.0,
+            0.0,
+        );
+        let mut settings = RenderSettings {
+            width: 70,
+            height: 35,
+            samples: 2,
+            max_depth: 8,
+            seed: 3,
+            threads: Some(1),
+            ..RenderSettings::default()
+        };
+        let single = render(&settings, &camera, &world, &Lights::default());
+        settings.threads = Some(4);
+        assert_eq!(
+            single,
+            render(&settings, &camera, &world, &Lights::default())
+        );
+        settings.seed = 4;
+        assert_ne!(
+            single,
+            render(&settings, &camera, &world, &Lights::default())
+        );
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let lamp: Arc<dyn Material> = Arc::new(DiffuseLight::new(&Vec3::from_float(20.0)));
+        let objects: Vec<Arc<dyn Intersect>> = vec![
+            Arc::new(Plane::new(
+                &Vec3::from_float(0.0),
+                &Vec3::new(0.0, 1.0, 0.0),
+                Arc::new(Lambertian::new(&Vec3::from_float(0.5))),
+            )),
+            Arc::new(Sphere::new(
+                &Vec3::new(-0.5, 0.5, 0.0),
+                0.3,
+                Arc::new(RoughConductor::named("gold", 0.3).unwrap()),
+            )),
+            Arc::new(Quad::new(
+                &Vec3::new(0.2, 1.5, -0.2),
+                &Vec3::new(0.3, 0.0, 0.0),
+                &Vec3::new(0.0, 0.0, 0.3),
+                lamp.clone(),
+            )),
+            Arc::new(Sphere::new(&Vec3::new(1.0, 1.0, -1.0), 0.1, lamp)),
+        ];
+        let lights = Lights::from_objects(&objects);
+        assert_eq!(lights.len(), 2);
+        let world = Intersectables::new(objects);
+        let background = Background::Color(Vec3::from_float(0.0));
+        let ray = || Ray::new(&Vec3::new(0.0, 1.0, 2.0), &Vec3::new(-0.2, -1.0, -2.0));
+
+        //both estimators converge to the same radiance, sampling the lights with far less noise
+        let trials = 40000;
+        let estimate = |lights: &Lights| {
+            let (mut mean, mut square) = (0.0, 0.0);
+            for sample in 0..trials {
+                let mut sampler = Sampler::new(5, 0, sample);
+                let value = color(&ray(), &world, lights, &background, 4, &mut sampler).sum();
+                mean += value / trials as f32;
+                square += value * value / trials as f32;
+            }
+            (mean, square - mean * mean)
+        };
+        let (walk, walk_variance) = estimate(&Lights::default());
+        let (sampled, sampled_variance) = estimate(&lights);
+        let error = ((walk_variance + sampled_variance) / trials as f32).sqrt();
+        assert!(
+            (walk - sampled).abs() < 4.0 * error,
+            "{} {} {}",
+            walk,
+            sampled,
+            error
+        );
+        assert!(
+            sampled_variance * 10.0 < walk_variance,
+            "{} {}",
+            sampled_variance,
+            walk_variance
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Seedable random numbers for reproducible renders.
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+/// source of every random decision made while rendering, seeded so that renders can be reproduced exactly
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    rng: Pcg32,
+}
+
+impl Sampler {
+    pub fn from_seed(seed: u64) -> Self {
+        Sampler {
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+
+    /// each sample of each pixel gets its own stream so no result depends on the order pixels are rendered in
+    pub fn new(seed: u64, pixel: u64, sample: u64) -> Self {
+        Sampler::from_seed(mix(mix(seed, pixel), sample))
+    }
+
+    /// uniform in [0, 1)
+    pub fn gen(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
+        self.rng.gen_range(low, high)
+    }
+
+    /// a fresh seed, e.g. for tables built once before rendering
+    pub fn gen_u64(&mut self) -> u64 {
+        self.rng.gen()
+    }
+}
+
+/// combine a seed with an index into a well mixed seed (splitmix64 finalizer)
+pub fn mix(seed: u64, index: u64) -> u64 {
+    let mut z = seed
+        ^ index
+            .wrapping_add(0x9E37_79B9_7F4A_7C15)
+            .wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        let mut a = Sampler::new(1, 2, 3);
+        let mut b = Sampler::new(1, 2, 3);
+        let mut c = Sampler::new(1, 3, 2);
+        let a: Vec<f32> = (0..16).map(|_| a.gen()).collect();
+        let b: Vec<f32> = (0..16).map(|_| b.gen()).collect();
+        let c: Vec<f32> = (0..16).map(|_| c.gen()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.iter().all(|x| (0.0..1.0).contains(x)));
+    }
+}
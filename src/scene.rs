@@ -0,0 +1,1584 @@
+//! TOML scene files and render settings.
+
+use super::bvh::*;
+use super::camera::*;
+use super::lights::*;
+use super::materials::*;
+use super::matrix::*;
+use super::microfacet::*;
+use super::noise::*;
+use super::obj::*;
+use super::output::*;
+use super::ray::*;
+use super::sampler::*;
+use super::shapes::*;
+use super::texture::*;
+use super::tonemap::*;
+use super::vec3::*;
+use super::volume::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        file: PathBuf,
+        error: io::Error,
+    },
+    /// syntax errors, unknown keys and wrongly typed values, toml reports the line
+    Parse {
+        file: PathBuf,
+        error: toml::de::Error,
+    },
+    UnknownMaterial {
+        file: PathBuf,
+        object: usize,
+        material: String,
+    },
+    Invalid {
+        file: PathBuf,
+        message: String,
+    },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            SceneError::Parse { file, error } => write!(f, "{}: {}", file.display(), error),
+            SceneError::UnknownMaterial {
+                file,
+                object,
+                material,
+            } => write!(
+                f,
+                "{}: object {} refers to unknown material '{}'",
+                file.display(),
+                object,
+                material
+            ),
+            SceneError::Invalid { file, message } => write!(f, "{}: {}", file.display(), message),
+            SceneError::Obj(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Parse { error, .. } => Some(error),
+            SceneError::Obj(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> Self {
+        SceneError::Obj(error)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub max_depth: u32,
+    pub output: PathBuf,
+    /// the image only depends on the seed, never on the number of threads
+    pub seed: u64,
+    /// defaults to one per core
+    pub threads: Option<usize>,
+    /// what rays that escape the scene see
+    pub background: Background,
+    /// how linear radiance becomes 8 bit output
+    pub tonemap: Tonemap,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 800,
+            height: 400,
+            samples: 100,
+            max_depth: 50,
+            output: PathBuf::from("test.ppm"),
+            seed: 0,
+            threads: None,
+            background: Background::Sky,
+            tonemap: Tonemap::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 || self.samples == 0 {
+            return Err("width, height and samples must be positive".to_string());
+        }
+        //catch a bad output path before spending time on the render
+        if ImageFormat::from_path(&self.output).is_none() {
+            return Err(format!(
+                "unsupported output format '{}', expected .png, .ppm or .hdr",
+                self.output.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// settings given on the command line, they take precedence over the scene file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub output: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+}
+
+impl Overrides {
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        if let Some(width) = self.width {
+            settings.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.height = height;
+        }
+        if let Some(samples) = self.samples {
+            settings.samples = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if let Some(output) = &self.output {
+            settings.output = output.clone();
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if self.threads.is_some() {
+            settings.threads = self.threads;
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "BackgroundDescription")]
+pub enum Background {
+    /// the blue to white gradient which also lights the scene
+    Sky,
+    /// a constant color, black leaves emissive materials as the only light
+    Color(Vec3),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Vec3 {
+        match self {
+            Background::Sky => {
+                let t = 0.5 * (ray.direction().y() + 1.0);
+                &(&Vec3::new(1.0, 1.0, 1.0) * (1.0 - t)) + &(&Vec3::new(0.5, 0.7, 1.0) * t)
+            }
+            Background::Color(color) => *color,
+        }
+    }
+}
+
+//a background is written either as a name or as an [r, g, b] color
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDescription {
+    Name(String),
+    Color([f32; 3]),
+}
+
+impl TryFrom<BackgroundDescription> for Background {
+    type Error = String;
+    fn try_from(description: BackgroundDescription) -> Result<Self, Self::Error> {
+        match description {
+            BackgroundDescription::Name(name) => match name.as_str() {
+                "sky" => Ok(Background::Sky),
+                "black" => Ok(Background::Color(Vec3::from_float(0.0))),
+                _ => Err(format!(
+                    "unknown background '{}', expected \"sky\", \"black\" or an [r, g, b] color",
+                    name
+                )),
+            },
+            BackgroundDescription::Color(color) => Ok(Background::Color(vec3(&color))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderSettings,
+    camera: CameraDescription,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialEntry>,
+    #[serde(default)]
+    objects: Vec<ObjectEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MaterialEntry {
+    #[serde(flatten)]
+    kind: MaterialDescription,
+    /// a tangent space normal map, the path is relative to the scene file
+    normal_map: Option<PathBuf>,
+    /// a height texture whose slope tilts the normal
+    bump: Option<BumpDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BumpDescription {
+    height: TextureDescription,
+    #[serde(default = "default_bump_strength")]
+    strength: f32,
+}
+
+fn default_bump_strength() -> f32 {
+    1.0
+}
+
+fn default_half() -> f32 {
+    0.5
+}
+
+fn default_clearcoat_gloss() -> f32 {
+    1.0
+}
+
+fn default_refraction_index() -> f32 {
+    1.5
+}
+
+impl MaterialEntry {
+    fn detail(
+        &self,
+        directory: &Path,
+        sampler: &mut Sampler,
+    ) -> Result<Option<SurfaceDetail>, SceneError> {
+        if let Some(path) = &self.normal_map {
+            //normal maps hold directions rather than colors, and tile like other mesh textures
+            let path = directory.join(path);
+            let map = ImageTexture::load_linear(&path)
+                .map_err(|error| SceneError::Io { file: path, error })?
+                .with_filter(Filter::Bilinear)
+                .with_wrap(Wrap::Repeat);
+            return Ok(Some(SurfaceDetail::NormalMap(Box::new(map))));
+        }
+        Ok(match &self.bump {
+            Some(bump) => Some(SurfaceDetail::BumpMap {
+                height: bump.height.build(directory, sampler)?,
+                strength: bump.strength,
+            }),
+            None => None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectEntry {
+    #[serde(flatten)]
+    shape: ObjectDescription,
+    transform: Option<TransformDescription>,
+    /// where the transform has got to at time 1, moving from transform at time 0
+    transform_end: Option<TransformDescription>,
+    /// fills the shape with fog or smoke instead of giving it a surface
+    medium: Option<MediumDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumDescription {
+    density: f32,
+    /// usually an isotropic material
+    material: String,
+}
+
+/// applied as scale, then rotations about x, y and z in degrees, then translate
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct TransformDescription {
+    scale: [f32; 3],
+    rotate: [f32; 3],
+    translate: [f32; 3],
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        TransformDescription {
+            scale: [1.0, 1.0, 1.0],
+            rotate: [0.0, 0.0, 0.0],
+            translate: [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl TransformDescription {
+    fn build(&self) -> Result<Transform, String> {
+        if self.scale.contains(&0.0) {
+            return Err("scale factors must be non-zero".to_string());
+        }
+        Ok(Transform::identity()
+            .scale(&vec3(&self.scale))
+            .rotate_x(self.rotate[0])
+            .rotate_y(self.rotate[1])
+            .rotate_z(self.rotate[2])
+            .translate(&vec3(&self.translate)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    lookfrom: [f32; 3],
+    lookat: [f32; 3],
+    #[serde(default = "default_vup")]
+    vup: [f32; 3],
+    vfov: f32,
+    #[serde(default)]
+    lens_radius: f32,
+    /// open and close times, objects move between times 0 and 1
+    #[serde(default)]
+    shutter: [f32; 2],
+}
+
+fn default_vup() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+//textures are written either as a plain [r, g, b] color or as a table with a type
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextureDescription {
+    Color([f32; 3]),
+    Texture(TextureKind),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureKind {
+    Checker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        #[serde(default = "default_checker_scale")]
+        scale: f32,
+    },
+    /// a png, jpeg, hdr or ppm image, the path is relative to the scene file
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        filter: Filter,
+        #[serde(default)]
+        wrap: Wrap,
+    },
+    /// a solid noise pattern blending between two colors
+    Noise {
+        pattern: Pattern,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default = "default_noise_colors")]
+        colors: [[f32; 3]; 2],
+        /// octaves of turbulence
+        depth: Option<u32>,
+        #[serde(default)]
+        smoothing: SmoothingName,
+        /// fixes the noise regardless of the render seed and the other textures
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SmoothingName {
+    Linear,
+    #[default]
+    Hermite,
+}
+
+fn default_noise_scale() -> f32 {
+    1.0
+}
+
+fn default_noise_colors() -> [[f32; 3]; 2] {
+    [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]]
+}
+
+fn default_checker_scale() -> f32 {
+    10.0
+}
+
+impl TextureDescription {
+    fn build(
+        &self,
+        directory: &Path,
+        sampler: &mut Sampler,
+    ) -> Result<Box<dyn Texture>, SceneError> {
+        Ok(match self {
+            TextureDescription::Color(color) => Box::new(SolidColor::new(&vec3(color))),
+            TextureDescription::Texture(TextureKind::Checker { even, odd, scale }) => {
+                Box::new(Checker::new(
+                    even.build(directory, sampler)?,
+                    odd.build(directory, sampler)?,
+                    *scale,
+                ))
+            }
+            TextureDescription::Texture(TextureKind::Image { path, filter, wrap }) => {
+                let path = directory.join(path);
+                let image = ImageTexture::load(&path)
+                    .map_err(|error| SceneError::Io { file: path, error })?;
+                Box::new(image.with_filter(*filter).with_wrap(*wrap))
+            }
+            TextureDescription::Texture(TextureKind::Noise {
+                pattern,
+                scale,
+                colors,
+                depth,
+                smoothing,
+                seed,
+            }) => {
+                let mut own = seed.map(Sampler::from_seed);
+                let sampler = own.as_mut().unwrap_or(sampler);
+                let smoothing = match smoothing {
+                    SmoothingName::Linear => Smoothing::Linear,
+                    SmoothingName::Hermite => Smoothing::Hermite,
+                };
+                let mut texture = NoiseTexture::new(
+                    *pattern,
+                    *scale,
+                    &vec3(&colors[0]),
+                    &vec3(&colors[1]),
+                    sampler,
+                )
+                .with_smoothing(smoothing);
+                if let Some(depth) = depth {
+                    texture = texture.with_depth(*depth);
+                }
+                Box::new(texture)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: TextureDescription,
+    },
+    Metal {
+        albedo: TextureDescription,
+        #[serde(default)]
+        fuzz: f32,
+    },
+    /// a rough microfacet metal, either a named metal or its complex index of refraction
+    Conductor {
+        metal: Option<String>,
+        eta: Option<[f32; 3]>,
+        k: Option<[f32; 3]>,
+        #[serde(default)]
+        roughness: f32,
+    },
+    /// a roughness makes frosted microfacet glass instead of fuzzy glass
+    Dielectric {
+        refraction_index: f32,
+        #[serde(default)]
+        fuzz: f32,
+        roughness: Option<f32>,
+        albedo: Option<TextureDescription>,
+    },
+    /// disney's principled bsdf, every parameter but the refraction index runs from 0 to 1
+    Principled {
+        base_color: TextureDescription,
+        #[serde(default)]
+        metallic: f32,
+        #[serde(default = "default_half")]
+        roughness: f32,
+        #[serde(default = "default_half")]
+        specular: f32,
+        #[serde(default)]
+        specular_tint: f32,
+        #[serde(default)]
+        sheen: f32,
+        #[serde(default = "default_half")]
+        sheen_tint: f32,
+        #[serde(default)]
+        clearcoat: f32,
+        #[serde(default = "default_clearcoat_gloss")]
+        clearcoat_gloss: f32,
+        #[serde(default)]
+        transmission: f32,
+        #[serde(default = "default_refraction_index")]
+        refraction_index: f32,
+        #[serde(default)]
+        subsurface: f32,
+    },
+    Light {
+        emit: [f32; 3],
+    },
+    /// scatters the same in every direction, for media
+    Isotropic {
+        albedo: TextureDescription,
+    },
+}
+
+impl MaterialDescription {
+    /// surfaces can take normal and bump maps, lights and media cannot
+    fn takes_detail(&self) -> bool {
+        matches!(
+            self,
+            MaterialDescription::Lambertian { .. }
+                | MaterialDescription::Metal { .. }
+                | MaterialDescription::Conductor { .. }
+                | MaterialDescription::Dielectric { .. }
+                | MaterialDescription::Principled { .. }
+        )
+    }
+
+    fn check(&self) -> Result<(), String> {
+        match self {
+            MaterialDescription::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                match (metal, eta, k) {
+                    (Some(metal), None, None) => {
+                        if conductor_ior(metal).is_none() {
+                            return Err(format!(
+                                "unknown metal '{}', expected one of {}",
+                                metal,
+                                CONDUCTORS.join(", ")
+                            ));
+                        }
+                    }
+                    (None, Some(_), Some(_)) => {}
+                    _ => return Err("a conductor needs either a metal or both eta and k".into()),
+                }
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err("roughness must be between 0 and 1".into());
+                }
+            }
+            MaterialDescription::Dielectric {
+                fuzz,
+                roughness: Some(roughness),
+                ..
+            } => {
+                if *fuzz != 0.0 {
+                    return Err("give either a fuzz or a roughness, not both".into());
+                }
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err("roughness must be between 0 and 1".into());
+                }
+            }
+            MaterialDescription::Principled {
+                base_color: _,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                refraction_index,
+                subsurface,
+            } => {
+                let parameters = [
+                    ("metallic", metallic),
+                    ("roughness", roughness),
+                    ("specular", specular),
+                    ("specular_tint", specular_tint),
+                    ("sheen", sheen),
+                    ("sheen_tint", sheen_tint),
+                    ("clearcoat", clearcoat),
+                    ("clearcoat_gloss", clearcoat_gloss),
+                    ("transmission", transmission),
+                    ("subsurface", subsurface),
+                ];
+                for (name, value) in parameters {
+                    if !(0.0..=1.0).contains(value) {
+                        return Err(format!("{} must be between 0 and 1", name));
+                    }
+                }
+                if *refraction_index <= 0.0 {
+                    return Err("refraction_index must be positive".into());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn build(
+        &self,
+        directory: &Path,
+        sampler: &mut Sampler,
+        detail: Option<SurfaceDetail>,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        Ok(match self {
+            MaterialDescription::Lambertian { albedo } => {
+                let mut material = Lambertian::textured(albedo.build(directory, sampler)?);
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                let mut material = Metal::textured(albedo.build(directory, sampler)?, *fuzz);
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                let mut material = match metal {
+                    Some(metal) => RoughConductor::named(metal, *roughness)
+                        .expect("conductors are checked before they are built"),
+                    None => RoughConductor::new(
+                        &vec3(&eta.unwrap_or_default()),
+                        &vec3(&k.unwrap_or_default()),
+                        *roughness,
+                    ),
+                };
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Dielectric {
+                refraction_index,
+                roughness: Some(roughness),
+                albedo,
+                ..
+            } => {
+                let mut material = match albedo {
+                    Some(albedo) => RoughDielectric::textured(
+                        albedo.build(directory, sampler)?,
+                        *refraction_index,
+                        *roughness,
+                    ),
+                    None => RoughDielectric::new(*refraction_index, *roughness),
+                };
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Dielectric {
+                refraction_index,
+                fuzz,
+                albedo,
+                roughness: None,
+            } => {
+                let mut material = match albedo {
+                    Some(albedo) => Dielectric::textured(
+                        albedo.build(directory, sampler)?,
+                        *refraction_index,
+                        *fuzz,
+                    ),
+                    None => Dielectric::new(*refraction_index, *fuzz),
+                };
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                refraction_index,
+                subsurface,
+            } => {
+                let mut material = Principled::textured(base_color.build(directory, sampler)?)
+                    .with_metallic(*metallic)
+                    .with_roughness(*roughness)
+                    .with_specular(*specular, *specular_tint)
+                    .with_sheen(*sheen, *sheen_tint)
+                    .with_clearcoat(*clearcoat, *clearcoat_gloss)
+                    .with_transmission(*transmission, *refraction_index)
+                    .with_subsurface(*subsurface);
+                if let Some(detail) = detail {
+                    material = material.with_detail(detail);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::Light { emit } => Arc::new(DiffuseLight::new(&vec3(emit))),
+            MaterialDescription::Isotropic { albedo } => {
+                Arc::new(Isotropic::textured(albedo.build(directory, sampler)?))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectDescription {
+    /// moves to center1 at time 1 when it is given
+    Sphere {
+        center: [f32; 3],
+        center1: Option<[f32; 3]>,
+        radius: f32,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        normals: Option<[[f32; 3]; 3]>,
+        material: String,
+    },
+    /// an infinite plane
+    Plane {
+        point: [f32; 3],
+        normal: [f32; 3],
+        material: String,
+    },
+    /// the parallelogram corner + s*u + t*v, facing u x v
+    Quad {
+        corner: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        material: String,
+    },
+    /// an axis aligned box between two opposite corners
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: String,
+    },
+    /// obj files carry their own materials through mtllib
+    Mesh { path: PathBuf },
+    /// a density grid file stretched over the box between min and max
+    Volume {
+        path: PathBuf,
+        min: [f32; 3],
+        max: [f32; 3],
+        #[serde(default)]
+        absorption: f32,
+        #[serde(default = "default_scattering")]
+        scattering: f32,
+        /// the Henyey-Greenstein asymmetry, positive scatters forwards
+        #[serde(default)]
+        g: f32,
+    },
+}
+
+fn default_scattering() -> f32 {
+    1.0
+}
+
+/// a scene owns its materials and objects so it can be built in one place, returned and shared
+/// across threads
+#[derive(Debug)]
+pub struct Scene {
+    pub settings: RenderSettings,
+    pub camera: Camera,
+    materials: BTreeMap<String, Arc<dyn Material>>,
+    objects: Vec<Arc<dyn Intersect>>,
+}
+
+impl Scene {
+    pub fn new(settings: RenderSettings, camera: Camera) -> Self {
+        Scene {
+            settings,
+            camera,
+            materials: BTreeMap::new(),
+            objects: vec![],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        Scene::load_with(path, &Overrides::default())
+    }
+
+    pub fn load_with<P: AsRef<Path>>(path: P, overrides: &Overrides) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+            file: path.to_path_buf(),
+            error,
+        })?;
+        Scene::parse_with(path, &source, overrides)
+    }
+
+    /// mesh paths are resolved relative to the directory of file
+    pub fn parse(file: &Path, source: &str) -> Result<Scene, SceneError> {
+        Scene::parse_with(file, source, &Overrides::default())
+    }
+
+    /// overrides are applied before the settings are checked and the camera is set up
+    pub fn parse_with(
+        file: &Path,
+        source: &str,
+        overrides: &Overrides,
+    ) -> Result<Scene, SceneError> {
+        let description: SceneFile = toml::from_str(source).map_err(|error| SceneError::Parse {
+            file: file.to_path_buf(),
+            error,
+        })?;
+        let mut settings = description.render;
+        overrides.apply(&mut settings);
+        settings.validate().map_err(|message| SceneError::Invalid {
+            file: file.to_path_buf(),
+            message,
+        })?;
+        let camera = &description.camera;
+        let [open, close] = camera.shutter;
+        if close < open {
+            return Err(SceneError::Invalid {
+                file: file.to_path_buf(),
+                message: "the shutter must close after it opens".to_string(),
+            });
+        }
+        let camera = Camera::new(
+            &vec3(&camera.lookfrom),
+            &vec3(&camera.lookat),
+            &vec3(&camera.vup),
+            camera.vfov,
+            settings.width as f32 / settings.height as f32,
+            camera.lens_radius,
+        )
+        .with_shutter(open, close);
+        let mut scene = Scene::new(settings, camera);
+
+        let directory = file.parent().unwrap_or_else(|| Path::new(""));
+        //noise textures draw from a stream of the render seed that no pixel uses
+        let mut sampler = Sampler::new(scene.settings.seed, u64::MAX, 0);
+        for (name, entry) in &description.materials {
+            let invalid = |message: &str| SceneError::Invalid {
+                file: file.to_path_buf(),
+                message: format!("material '{}': {}", name, message),
+            };
+            entry.kind.check().map_err(|message| invalid(&message))?;
+            if entry.normal_map.is_some() && entry.bump.is_some() {
+                return Err(invalid("give either a normal_map or a bump, not both"));
+            }
+            let detail = entry.detail(directory, &mut sampler)?;
+            if detail.is_some() && !entry.kind.takes_detail() {
+                return Err(invalid(
+                    "only surface materials take normal and bump maps, not lights or isotropic media",
+                ));
+            }
+            scene.add_shared_material(name, entry.kind.build(directory, &mut sampler, detail)?);
+        }
+        let invalid = |object: usize, message: &str| SceneError::Invalid {
+            file: file.to_path_buf(),
+            message: format!("object {}: {}", object, message),
+        };
+        //every use of an obj file shares one copy of its triangles
+        let mut meshes: HashMap<PathBuf, Arc<dyn Intersect>> = HashMap::new();
+        let mut grids: HashMap<PathBuf, Arc<DensityGrid>> = HashMap::new();
+        for (i, entry) in description.objects.iter().enumerate() {
+            let material = |name: &str| {
+                scene
+                    .material(name)
+                    .ok_or_else(|| SceneError::UnknownMaterial {
+                        file: file.to_path_buf(),
+                        object: i,
+                        material: name.to_string(),
+                    })
+            };
+            let object: Arc<dyn Intersect> = match &entry.shape {
+                ObjectDescription::Sphere {
+                    center,
+                    center1: None,
+                    radius,
+                    material: name,
+                } => {
+                    let sphere = Sphere::new(&vec3(center), *radius, material(name)?);
+                    Arc::new(sphere)
+                }
+                ObjectDescription::Sphere {
+                    center,
+                    center1: Some(center1),
+                    radius,
+                    material: name,
+                } => {
+                    let sphere = MovingSphere::new(
+                        &vec3(center),
+                        &vec3(center1),
+                        0.0,
+                        1.0,
+                        *radius,
+                        material(name)?,
+                    );
+                    Arc::new(sphere)
+                }
+                ObjectDescription::Triangle {
+                    vertices,
+                    normals,
+                    material: name,
+                } => {
+                    let vertices = [vec3(&vertices[0]), vec3(&vertices[1]), vec3(&vertices[2])];
+                    let triangle = match normals {
+                        Some(n) => Triangle::smooth(
+                            vertices,
+                            [vec3(&n[0]), vec3(&n[1]), vec3(&n[2])],
+                            material(name)?,
+                        ),
+                        None => Triangle::new(vertices, material(name)?),
+                    };
+                    Arc::new(triangle)
+                }
+                ObjectDescription::Plane {
+                    point,
+                    normal,
+                    material: name,
+                } => {
+                    if vec3(normal).squared_length() == 0.0 {
+                        return Err(invalid(i, "a plane needs a non-zero normal"));
+                    }
+                    let plane = Plane::new(&vec3(point), &vec3(normal), material(name)?);
+                    Arc::new(plane)
+                }
+                ObjectDescription::Quad {
+                    corner,
+                    u,
+                    v,
+                    material: name,
+                } => {
+                    if vec3(u).cross(&vec3(v)).squared_length() == 0.0 {
+                        return Err(invalid(i, "a quad needs two edges that are not parallel"));
+                    }
+                    let quad = Quad::new(&vec3(corner), &vec3(u), &vec3(v), material(name)?);
+                    Arc::new(quad)
+                }
+                ObjectDescription::Box {
+                    min,
+                    max,
+                    material: name,
+                } => {
+                    let shape = BoxShape::new(&vec3(min), &vec3(max), material(name)?);
+                    Arc::new(shape)
+                }
+                ObjectDescription::Mesh { path } => {
+                    let path = directory.join(path);
+                    match meshes.get(&path) {
+                        Some(mesh) => mesh.clone(),
+                        None => {
+                            let mesh: Arc<dyn Intersect> = Arc::new(Obj::load(&path)?.mesh());
+                            meshes.insert(path, mesh.clone());
+                            mesh
+                        }
+                    }
+                }
+                ObjectDescription::Volume {
+                    path,
+                    min,
+                    max,
+                    absorption,
+                    scattering,
+                    g,
+                } => {
+                    if *absorption < 0.0 || *scattering < 0.0 {
+                        return Err(invalid(i, "absorption and scattering must not be negative"));
+                    }
+                    let path = directory.join(path);
+                    let grid = match grids.get(&path) {
+                        Some(grid) => grid.clone(),
+                        None => {
+                            let grid =
+                                DensityGrid::load(&path).map_err(|error| SceneError::Io {
+                                    file: path.clone(),
+                                    error,
+                                })?;
+                            let grid = Arc::new(grid);
+                            grids.insert(path, grid.clone());
+                            grid
+                        }
+                    };
+                    let volume =
+                        GridVolume::new(grid, &vec3(min), &vec3(max), *absorption, *scattering, *g);
+                    Arc::new(volume)
+                }
+            };
+            let build = |transform: &TransformDescription| {
+                transform.build().map_err(|message| invalid(i, &message))
+            };
+            let object: Arc<dyn Intersect> = match (&entry.transform, &entry.transform_end) {
+                (Some(transform), None) => Arc::new(Instance::new(object, build(transform)?)),
+                (start, Some(end)) => {
+                    let start = match start {
+                        Some(start) => build(start)?,
+                        None => Transform::identity(),
+                    };
+                    let motion = AnimatedTransform::new(start, build(end)?, 0.0, 1.0);
+                    Arc::new(MovingInstance::new(object, motion))
+                }
+                (None, None) => object,
+            };
+            //the medium fills the placed shape so its density is in world units
+            match &entry.medium {
+                Some(medium) => {
+                    if medium.density <= 0.0 {
+                        return Err(invalid(i, "a medium needs a positive density"));
+                    }
+                    let phase = material(&medium.material)?;
+                    scene.add(ConstantMedium::new(object, medium.density, phase));
+                }
+                None => scene.add_shared(object),
+            }
+        }
+        Ok(scene)
+    }
+
+    /// replaces any material already registered under name
+    pub fn add_material<M: Material + 'static>(
+        &mut self,
+        name: &str,
+        material: M,
+    ) -> Arc<dyn Material> {
+        self.add_shared_material(name, Arc::new(material))
+    }
+
+    pub fn add_shared_material(
+        &mut self,
+        name: &str,
+        material: Arc<dyn Material>,
+    ) -> Arc<dyn Material> {
+        self.materials.insert(name.to_string(), material.clone());
+        material
+    }
+
+    pub fn material(&self, name: &str) -> Option<Arc<dyn Material>> {
+        self.materials.get(name).cloned()
+    }
+
+    /// sorted by name
+    pub fn material_names(&self) -> Vec<&str> {
+        self.materials.keys().map(|name| name.as_str()).collect()
+    }
+
+    pub fn add<T: Intersect + 'static>(&mut self, object: T) {
+        self.objects.push(Arc::new(object));
+    }
+
+    pub fn add_shared(&mut self, object: Arc<dyn Intersect>) {
+        self.objects.push(object);
+    }
+
+    pub fn objects(&self) -> &[Arc<dyn Intersect>] {
+        &self.objects
+    }
+
+    /// the objects share their storage with the scene, so building the tree copies no geometry
+    pub fn world(&self) -> Bvh<Arc<dyn Intersect>> {
+        Bvh::new(self.objects.clone())
+    }
+
+    /// the emissive objects that can be sampled for direct lighting
+    pub fn lights(&self) -> Lights {
+        Lights::from_objects(&self.objects)
+    }
+}
+
+fn vec3(v: &[f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCENE: &str = r#"
+[render]
+width = 200
+height = 100
+samples = 4
+
+[camera]
+lookfrom = [0.0, 1.0, 3.0]
+lookat = [0.0, 1.0, 0.0]
+vfov = 60.0
+
+[materials.red]
+type = "lambertian"
+albedo = [0.8, 0.1, 0.1]
+
+[materials.floor]
+type = "lambertian"
+albedo = { type = "checker", even = [0.9, 0.9, 0.9], odd = [0.1, 0.1, 0.1] }
+
+[materials.glass]
+type = "dielectric"
+refraction_index = 1.5
+
+[[objects]]
+type = "sphere"
+center = [0.0, 1.0, 0.0]
+radius = 1.0
+material = "glass"
+
+[[objects]]
+type = "triangle"
+vertices = [[-5.0, 0.0, -5.0], [5.0, 0.0, -5.0], [0.0, 0.0, 5.0]]
+material = "red"
+"#;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse(Path::new("test.toml"), source)
+    }
+
+    #[test]
+    fn test_parse() {
+        let scene = parse(SCENE).unwrap();
+        assert_eq!(scene.settings.width, 200);
+        assert_eq!(scene.settings.max_depth, 50);
+        assert_eq!(scene.material_names(), vec!["floor", "glass", "red"]);
+        assert!(scene.material("red").is_some());
+        assert_eq!(scene.world().len(), 2);
+    }
+
+    #[test]
+    fn test_build_in_code() {
+        fn shared<T: Send + Sync>(_: &T) {}
+        let mut scene = parse(SCENE).unwrap();
+        shared(&scene);
+        let blue = scene.add_material("blue", Lambertian::new(&Vec3::new(0.1, 0.1, 0.9)));
+        scene.add(Sphere::new(&Vec3::new(2.0, 1.0, 0.0), 0.5, blue));
+        //the sphere keeps the material it was given even when the name is reused
+        scene.add_material("blue", Metal::new(&Vec3::from_float(0.9), 0.0));
+        assert_eq!(
+            scene.material_names(),
+            vec!["blue", "floor", "glass", "red"]
+        );
+        assert_eq!(scene.objects().len(), 3);
+        assert_eq!(scene.world().len(), 3);
+    }
+
+    #[test]
+    fn test_flat_shapes() {
+        let objects = r#"
+[[objects]]
+type = "plane"
+point = [0.0, -1.0, 0.0]
+normal = [0.0, 1.0, 0.0]
+material = "floor"
+
+[[objects]]
+type = "quad"
+corner = [0.0, 0.0, 0.0]
+u = [1.0, 0.0, 0.0]
+v = [0.0, 1.0, 0.0]
+material = "red"
+
+[[objects]]
+type = "box"
+min = [0.0, 0.0, 0.0]
+max = [1.0, 2.0, 3.0]
+material = "red"
+"#;
+        let scene = parse(&format!("{}{}", SCENE, objects)).unwrap();
+        assert_eq!(scene.objects().len(), 5);
+        //the unbounded plane is kept beside the tree but is still part of the world
+        assert_eq!(scene.world().len(), 5);
+        let degenerate = objects.replace("v = [0.0, 1.0, 0.0]", "v = [2.0, 0.0, 0.0]");
+        let error = parse(&format!("{}{}", SCENE, degenerate))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("object 3: a quad needs"), "{}", error);
+    }
+
+    #[test]
+    fn test_background() {
+        let scene =
+            parse(&SCENE.replace("samples = 4", "samples = 4\nbackground = \"black\"")).unwrap();
+        assert_eq!(
+            scene.settings.background,
+            Background::Color(Vec3::from_float(0.0))
+        );
+        let source = SCENE.replace("samples = 4", "samples = 4\nbackground = [0.1, 0.2, 0.3]");
+        let scene = parse(&source).unwrap();
+        assert_eq!(
+            scene.settings.background,
+            Background::Color(Vec3::new(0.1, 0.2, 0.3))
+        );
+        let source = SCENE.replace("samples = 4", "samples = 4\nbackground = \"night\"");
+        let error = parse(&source).unwrap_err().to_string();
+        assert!(error.contains("unknown background 'night'"), "{}", error);
+    }
+
+    #[test]
+    fn test_tonemap() {
+        assert_eq!(parse(SCENE).unwrap().settings.tonemap, Tonemap::default());
+        let source = SCENE.replace(
+            "samples = 4",
+            "samples = 4\ntonemap = { exposure = -1.0, operator = \"aces\", transfer = 2.2 }",
+        );
+        let scene = parse(&source).unwrap();
+        assert_eq!(
+            scene.settings.tonemap,
+            Tonemap {
+                exposure: -1.0,
+                operator: ToneOperator::Aces,
+                transfer: Transfer::Gamma(2.2),
+            }
+        );
+        let source = SCENE.replace(
+            "samples = 4",
+            "samples = 4\ntonemap = { transfer = \"rec709\" }",
+        );
+        let error = parse(&source).unwrap_err().to_string();
+        assert!(error.contains("unknown transfer 'rec709'"), "{}", error);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = Overrides {
+            width: Some(64),
+            output: Some(PathBuf::from("out.hdr")),
+            threads: Some(2),
+            ..Overrides::default()
+        };
+        let scene = Scene::parse_with(Path::new("test.toml"), SCENE, &overrides).unwrap();
+        assert_eq!(scene.settings.width, 64);
+        assert_eq!(scene.settings.samples, 4);
+        assert_eq!(scene.settings.output, PathBuf::from("out.hdr"));
+        assert_eq!(scene.settings.threads, Some(2));
+        let overrides = Overrides {
+            output: Some(PathBuf::from("out.jpg")),
+            ..Overrides::default()
+        };
+        let error = Scene::parse_with(Path::new("test.toml"), SCENE, &overrides)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("unsupported output format 'out.jpg'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let objects = r#"
+[[objects]]
+type = "mesh"
+path = "cube.obj"
+
+[[objects]]
+type = "mesh"
+path = "cube.obj"
+transform = { scale = [2.0, 2.0, 2.0], rotate = [0.0, 90.0, 0.0], translate = [3.0, 0.0, 0.0] }
+"#;
+        let source = format!("{}{}", SCENE, objects);
+        let scene = Scene::parse(Path::new("scenes/test.toml"), &source).unwrap();
+        assert_eq!(scene.objects().len(), 4);
+        let bbox = scene.objects()[3].bounding_box().unwrap();
+        assert!((bbox.max().x() - 4.0).abs() < 1e-4, "{:?}", bbox);
+        let flat = source.replace("scale = [2.0, 2.0, 2.0]", "scale = [2.0, 0.0, 2.0]");
+        let error = Scene::parse(Path::new("scenes/test.toml"), &flat)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("object 3: scale factors must be non-zero"),
+            "{}",
+            error
+        );
+        let typo = source.replace("rotate =", "rotation =");
+        let error = parse(&typo).unwrap_err().to_string();
+        assert!(error.contains("unknown field `rotation`"), "{}", error);
+    }
+
+    #[test]
+    fn test_motion() {
+        let objects = r#"
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+center1 = [0.0, 2.0, 0.0]
+radius = 0.5
+material = "red"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 0.5
+material = "red"
+transform_end = { translate = [4.0, 0.0, 0.0] }
+"#;
+        let source = format!("{}{}", SCENE, objects)
+            .replace("vfov = 60.0", "vfov = 60.0\nshutter = [0.0, 0.5]");
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.camera.shutter(), (0.0, 0.5));
+        let rising = scene.objects()[2].bounding_box().unwrap();
+        assert_eq!((rising.min().y(), rising.max().y()), (-0.5, 2.5));
+        let sliding = scene.objects()[3].bounding_box().unwrap();
+        assert!((sliding.max().x() - 4.5).abs() < 1e-5, "{:?}", sliding);
+        let backwards = source.replace("shutter = [0.0, 0.5]", "shutter = [0.5, 0.0]");
+        let error = parse(&backwards).unwrap_err().to_string();
+        assert!(
+            error.contains("shutter must close after it opens"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_medium() {
+        let objects = r#"
+[materials.smoke]
+type = "isotropic"
+albedo = [0.2, 0.2, 0.2]
+
+[[objects]]
+type = "box"
+min = [-1.0, 0.0, -1.0]
+max = [1.0, 2.0, 1.0]
+material = "red"
+medium = { density = 0.5, material = "smoke" }
+"#;
+        let source = format!("{}{}", SCENE, objects);
+        let scene = parse(&source).unwrap();
+        assert_eq!(scene.objects().len(), 3);
+        let bbox = scene.objects()[2].bounding_box().unwrap();
+        assert_eq!(bbox.max().y(), 2.0);
+        let error = parse(&source.replace("\"smoke\" }", "\"fog\" }"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown material 'fog'"), "{}", error);
+        let error = parse(&source.replace("density = 0.5", "density = 0.0"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("object 2: a medium needs a positive density"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_volume() {
+        let objects = r#"
+[[objects]]
+type = "volume"
+path = "cloud.grid"
+min = [-2.0, 0.5, -1.5]
+max = [2.0, 3.5, 1.5]
+scattering = 2.0
+"#;
+        let source = format!("{}{}", SCENE, objects);
+        let scene = Scene::parse(Path::new("scenes/test.toml"), &source).unwrap();
+        let bbox = scene.objects()[2].bounding_box().unwrap();
+        assert_eq!(bbox.max().y(), 3.5);
+        let error = Scene::parse(
+            Path::new("scenes/test.toml"),
+            &source.replace("cloud", "fog"),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("scenes/fog.grid"), "{}", error);
+        let error = parse(&source.replace("scattering = 2.0", "scattering = -2.0"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("object 2: absorption and scattering must not be negative"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_noise_texture() {
+        let materials = r#"
+[materials.marble]
+type = "lambertian"
+albedo = { type = "noise", pattern = "marble", scale = 4.0, colors = [[0.1, 0.1, 0.1], [0.9, 0.9, 0.9]] }
+
+[materials.wood]
+type = "metal"
+albedo = { type = "noise", pattern = "wood", seed = 3, smoothing = "linear" }
+"#;
+        let source = format!("{}{}", SCENE, materials);
+        //the color the material gives a ray hitting a unit sphere around the origin
+        let albedo = |source: &str, name: &str| {
+            let scene = parse(source).unwrap();
+            let sphere = Sphere::new(
+                &Vec3::new(0.0, 0.0, 0.0),
+                1.0,
+                scene.material(name).unwrap(),
+            );
+            let ray = Ray::new(&Vec3::new(0.3, 0.2, 5.0), &Vec3::new(0.0, 0.0, -1.0));
+            let mut sampler = Sampler::from_seed(0);
+            let hit = sphere
+                .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+                .unwrap();
+            hit.scatter(&ray, &mut sampler).unwrap().attenuation
+        };
+        let seeded = source.replace("samples = 4", "samples = 4\nseed = 12");
+        assert_eq!(albedo(&source, "marble"), albedo(&source, "marble"));
+        assert_ne!(albedo(&source, "marble"), albedo(&seeded, "marble"));
+        //an explicit seed ignores the render seed
+        assert_eq!(albedo(&source, "wood"), albedo(&seeded, "wood"));
+        let error = parse(&source.replace("\"wood\", seed", "\"plaid\", seed"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("materials.wood"), "{}", error);
+    }
+
+    #[test]
+    fn test_surface_detail() {
+        let materials = r#"
+[materials.bumpy]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+bump = { height = { type = "noise", pattern = "smooth" }, strength = 0.2 }
+
+[materials.tiled]
+type = "metal"
+albedo = [0.9, 0.9, 0.9]
+normal_map = "missing_normal.png"
+"#;
+        let source = format!("{}{}", SCENE, materials);
+        let error = parse(&source).unwrap_err().to_string();
+        assert!(error.starts_with("missing_normal.png: "), "{}", error);
+        let source = source.replace("normal_map = \"missing_normal.png\"", "");
+        assert!(parse(&source).is_ok());
+        let both = source.replace(
+            "strength = 0.2 }",
+            "strength = 0.2 }\nnormal_map = \"n.png\"",
+        );
+        let error = parse(&both).unwrap_err().to_string();
+        assert!(
+            error.contains("material 'bumpy': give either a normal_map or a bump, not both"),
+            "{}",
+            error
+        );
+        let light = source.replace(
+            "type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+            "type = \"light\"\nemit = [1.0, 1.0, 1.0]",
+        );
+        let error = parse(&light).unwrap_err().to_string();
+        assert!(
+            error.contains("only surface materials take normal and bump maps"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_microfacet() {
+        let materials = r#"
+[materials.gold]
+type = "conductor"
+metal = "gold"
+roughness = 0.3
+
+[materials.custom]
+type = "conductor"
+eta = [0.2, 0.9, 1.1]
+k = [3.9, 2.4, 2.2]
+
+[materials.frosted]
+type = "dielectric"
+refraction_index = 1.5
+roughness = 0.2
+"#;
+        let source = format!("{}{}", SCENE, materials);
+        let scene = parse(&source).unwrap();
+        assert!(scene.material("gold").is_some());
+        assert!(scene.material("frosted").is_some());
+
+        let unknown = source.replace("metal = \"gold\"", "metal = \"brass\"");
+        let error = parse(&unknown).unwrap_err().to_string();
+        assert!(
+            error.contains("material 'gold': unknown metal 'brass', expected one of gold"),
+            "{}",
+            error
+        );
+        let half = source.replace("k = [3.9, 2.4, 2.2]", "");
+        let error = parse(&half).unwrap_err().to_string();
+        assert!(
+            error.contains("either a metal or both eta and k"),
+            "{}",
+            error
+        );
+        let fuzzy = source.replace("roughness = 0.2", "roughness = 0.2\nfuzz = 0.1");
+        let error = parse(&fuzzy).unwrap_err().to_string();
+        assert!(error.contains("either a fuzz or a roughness"), "{}", error);
+        let rough = source.replace("roughness = 0.3", "roughness = 1.5");
+        let error = parse(&rough).unwrap_err().to_string();
+        assert!(
+            error.contains("roughness must be between 0 and 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_principled() {
+        let materials = r#"
+[materials.car_paint]
+type = "principled"
+base_color = [0.6, 0.05, 0.05]
+metallic = 0.3
+roughness = 0.35
+clearcoat = 1.0
+clearcoat_gloss = 0.9
+
+[materials.velvet]
+type = "principled"
+base_color = { type = "checker", even = [0.2, 0.1, 0.4], odd = [0.4, 0.2, 0.6] }
+sheen = 1.0
+"#;
+        let source = format!("{}{}", SCENE, materials);
+        let scene = parse(&source).unwrap();
+        assert!(scene.material("car_paint").is_some());
+        assert!(scene.material("velvet").is_some());
+        let source = source.replace("metallic = 0.3", "metallic = 1.3");
+        let error = parse(&source).unwrap_err().to_string();
+        assert!(
+            error.contains("material 'car_paint': metallic must be between 0 and 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let source = SCENE.replace("radius = 1.0", "radius = 1.0\nradios = 2.0");
+        let error = parse(&source).unwrap_err().to_string();
+        assert!(error.contains("unknown field `radios`"), "{}", error);
+        assert!(error.contains("line"), "{}", error);
+    }
+
+    #[test]
+    fn test_dangling_material() {
+        let source = SCENE.replace("material = \"red\"", "material = \"blue\"");
+        let error = parse(&source).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "test.toml: object 1 refers to unknown material 'blue'"
+        );
+    }
+}
@@ -1,104 +1,287 @@
+//! Geometry that rays can hit, and the hit records they return.
+
+use super::aabb::*;
+use super::bvh::*;
 use super::materials::*;
+use super::matrix::*;
 use super::ray::*;
+use super::sampler::*;
+use super::texture::*;
 use super::vec3::*;
 use core::fmt::Debug;
+use std::f32::consts;
+use std::sync::Arc;
+
+/// anything a ray can hit, from a single shape up to a whole world
+pub trait Intersect: Debug + Send + Sync {
+    /// the closest hit with a distance in (t_min, t_max), media draw where the ray scatters from
+    /// the path's sampler
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>>;
+    /// None for objects with no finite extent
+    fn bounding_box(&self) -> Option<Aabb>;
+    /// whether the object emits light and can aim shadow rays at itself with sample_point
+    fn is_light(&self) -> bool {
+        false
+    }
+    /// a point on the surface for a shadow ray from origin to aim at, None for objects that
+    /// cannot be sampled
+    fn sample_point(&self, _origin: &Vec3, _sampler: &mut Sampler) -> Option<Vec3> {
+        None
+    }
+    /// the density over solid angle, seen from the ray's origin, with which sample_point picks
+    /// the point where the ray hit this object
+    fn pdf(&self, _ray: &Ray, _hit: &Hit) -> f32 {
+        0.0
+    }
+}
+
+impl<T: Intersect + ?Sized> Intersect for &T {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        (**self).intersect(ray, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+    fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        (**self).sample_point(origin, sampler)
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        (**self).pdf(ray, hit)
+    }
+}
+
+impl<T: Intersect + ?Sized> Intersect for Box<T> {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        (**self).intersect(ray, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+    fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        (**self).sample_point(origin, sampler)
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        (**self).pdf(ray, hit)
+    }
+}
 
-pub trait Intersect: Debug {
-    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit>;
+impl<T: Intersect + ?Sized> Intersect for Arc<T> {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        (**self).intersect(ray, t_min, t_max, sampler)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+    fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        (**self).sample_point(origin, sampler)
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        (**self).pdf(ray, hit)
+    }
 }
 
+/// a single surface with a normal and a material
 pub trait Normal: Intersect {
     fn normal(&self, point: &Vec3) -> Ray;
     fn material(&self) -> &dyn Material;
 }
 
+/// where a ray met a surface
 #[derive(Debug)]
 pub struct Hit<'a> {
     pub point: Vec3,
     pub distance: f32,
     pub object: &'a dyn Normal,
+    /// unit surface normal in world space
+    pub normal: Vec3,
+    /// weights of the second and third vertex when the object is a triangle
+    pub barycentric: Option<(f32, f32)>,
+    /// surface coordinates used to look up textures
+    pub uv: (f32, f32),
+    /// frame around the normal with the tangent along increasing u where the shape knows it,
+    /// normal and bump maps are given in it
+    pub shading: Onb,
 }
 
 impl<'a> Hit<'a> {
     pub fn new(point: &Vec3, distance: f32, object: &'a dyn Normal) -> Self {
+        let normal = *object.normal(point).direction();
         Hit {
-            point: point.clone(),
+            point: *point,
             distance,
             object,
+            normal,
+            barycentric: None,
+            uv: (0.0, 0.0),
+            shading: Onb::from_normal(&normal),
+        }
+    }
+
+    /// replace the normal, keeping the shading frame's tangents as close as they can stay
+    pub fn with_normal(mut self, normal: &Vec3) -> Self {
+        let frame = self.shading;
+        self.normal = *normal;
+        self.with_tangents(&frame.tangent, &frame.bitangent)
+    }
+
+    /// orient the shading frame by the directions in which u and v grow, the bitangent follows
+    /// dpdv so mirrored uv layouts keep their normal maps the right way up
+    pub fn with_tangents(mut self, dpdu: &Vec3, dpdv: &Vec3) -> Self {
+        self.shading = Onb::from_normal_tangent(&self.normal, dpdu);
+        if self.shading.bitangent.dot(dpdv) < 0.0 {
+            self.shading.bitangent = -self.shading.bitangent;
         }
+        self
+    }
+
+    pub fn with_uv(mut self, u: f32, v: f32) -> Self {
+        self.uv = (u, v);
+        self
+    }
+
+    pub fn with_barycentric(mut self, u: f32, v: f32) -> Self {
+        self.barycentric = Some((u, v));
+        self
+    }
+
+    /// interpolate per vertex attributes using the barycentric coordinates of the hit
+    pub fn interpolate(&self, attributes: &[Vec3; 3]) -> Option<Vec3> {
+        let (u, v) = self.barycentric?;
+        Some(&(&(&attributes[0] * (1.0 - u - v)) + &(&attributes[1] * u)) + &(&attributes[2] * v))
     }
 
-    pub fn normal(&self) -> Ray {
-        self.object.normal(&self.point)
+    pub fn scatter(&self, ray: &Ray, sampler: &mut Sampler) -> Option<ScatterRecord> {
+        self.object.material().scatter(ray, self, sampler)
     }
 
-    pub fn collide(&self, ray: &Ray) -> Ray {
-        self.object.material().collide(ray, self)
+    /// the material's bsdf times the cosine for light arriving from direction
+    pub fn eval(&self, ray: &Ray, direction: &Vec3) -> Vec3 {
+        self.object.material().eval(ray, self, direction)
     }
 
-    pub fn albedo(&self) -> &Vec3 {
-        self.object.material().albedo()
+    /// the density with which scatter picks direction
+    pub fn pdf(&self, ray: &Ray, direction: &Vec3) -> f32 {
+        self.object.material().pdf(ray, self, direction)
+    }
+
+    pub fn texture_value(&self, texture: &dyn Texture) -> Vec3 {
+        texture.value(self.uv.0, self.uv.1, &self.point)
+    }
+
+    pub fn emitted(&self) -> Vec3 {
+        self.object.material().emitted(self)
     }
 }
 
-#[derive(Debug)]
-pub struct Intersectables<'a> {
-    pub objects: Vec<&'a dyn Normal>,
+/// a flat list of objects, tested one after another
+#[derive(Debug, Clone, Default)]
+pub struct Intersectables {
+    pub objects: Vec<Arc<dyn Intersect>>,
 }
 
-impl<'a> Intersectables<'a> {
-    pub fn new(objects: Vec<&'a dyn Normal>) -> Self {
+impl Intersectables {
+    pub fn new(objects: Vec<Arc<dyn Intersect>>) -> Self {
         Intersectables { objects }
     }
 
-    pub fn push(&mut self, object: &'a dyn Normal) {
+    pub fn push(&mut self, object: Arc<dyn Intersect>) {
         self.objects.push(object);
     }
+
+    pub fn into_bvh(self) -> Bvh<Arc<dyn Intersect>> {
+        Bvh::new(self.objects)
+    }
 }
 
-impl<'a> Intersect for Intersectables<'a> {
-    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+impl Intersect for Intersectables {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
         let mut ret: Option<Hit> = None;
         for i in &self.objects {
-            let temp = (*i).intersect(ray, t_min, t_max);
+            let temp = (*i).intersect(ray, t_min, t_max, sampler);
             //need to check if temp exists and if so if it is closer than we currently have
-            match &temp {
-                //if a hit
-                Some(temp_hit) => {
-                    //check if ret is None
-                    match (&ret).as_ref() {
-                        //if we already had a hit
-                        Some(ret_hit) => {
-                            //if our existing hit is further than our latest then our latest is our new existing hit
-                            if ret_hit.distance > temp_hit.distance {
-                                ret = temp;
-                            }
-                        }
-                        //ret is None then we set temp to our new hit
-                        None => {
+            if let Some(temp_hit) = &temp {
+                //check if ret is None
+                match ret.as_ref() {
+                    //if we already had a hit
+                    Some(ret_hit) => {
+                        //if our existing hit is further than our latest then our latest is our new existing hit
+                        if ret_hit.distance > temp_hit.distance {
                             ret = temp;
                         }
                     }
+                    //ret is None then we set temp to our new hit
+                    None => {
+                        ret = temp;
+                    }
                 }
-                //no hit then don't do anything
-                None => {}
             }
         }
         ret
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut ret: Option<Aabb> = None;
+        for i in &self.objects {
+            let bbox = i.bounding_box()?;
+            ret = Some(match ret {
+                Some(acc) => acc.union(&bbox),
+                None => bbox,
+            });
+        }
+        ret
+    }
 }
 
 #[derive(Debug)]
-pub struct Sphere<'a> {
+pub struct Sphere {
     center: Vec3,
     radius: f32,
-    material: &'a dyn Material,
+    material: Arc<dyn Material>,
 }
 
-impl<'a> Sphere<'a> {
-    pub fn new(center: &Vec3, radius: f32, material: &'a dyn Material) -> Self {
+impl Sphere {
+    pub fn new(center: &Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
         Sphere {
-            center: center.clone(),
+            center: *center,
             radius,
             material,
         }
@@ -109,10 +292,33 @@ impl<'a> Sphere<'a> {
     pub fn radius(&self) -> f32 {
         self.radius
     }
+    /// spherical coordinates of the point: u around the y axis starting at -x, v from the bottom pole up
+    pub fn uv(&self, point: &Vec3) -> (f32, f32) {
+        sphere_uv(&self.center, self.radius, point)
+    }
+}
+
+fn sphere_uv(center: &Vec3, radius: f32, point: &Vec3) -> (f32, f32) {
+    let p = &(point - center) / radius.abs();
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + consts::PI;
+    (phi / (2.0 * consts::PI), theta / consts::PI)
+}
+
+//the direction u grows in around the sphere's axis, v always grows upwards
+fn sphere_tangent(center: &Vec3, point: &Vec3) -> Vec3 {
+    let p = point - center;
+    Vec3::new(p.z(), 0.0, -p.x())
 }
 
-impl<'a> Intersect for Sphere<'a> {
-    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+impl Intersect for Sphere {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
         let a = 1.0;
         let b = 2.0 * ray.direction().dot(&(ray.origin() - &(self.center)));
         let c = ray.origin().squared_length() - 2.0 * ray.origin().dot(&(self.center))
@@ -137,16 +343,1400 @@ impl<'a> Intersect for Sphere<'a> {
             } else {
                 return None;
             }
-            Some(Hit::new(&ray.parameterization(t), t, self))
+            let point = ray.parameterization(t);
+            let (u, v) = self.uv(&point);
+            let dpdu = sphere_tangent(&self.center, &point);
+            Some(
+                Hit::new(&point, t, self)
+                    .with_uv(u, v)
+                    .with_tangents(&dpdu, &Vec3::new(0.0, 1.0, 0.0)),
+            )
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        //negative radii are used for hollow spheres so the extent uses the magnitude
+        let r = Vec3::from_float(self.radius.abs());
+        Some(Aabb::new(&(&self.center - &r), &(&self.center + &r)))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.emits()
+    }
+
+    //directions in the cone the sphere fills as seen from origin, uniformly over solid angle
+    fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let radius = self.radius.abs();
+        let to_center = &self.center - origin;
+        let distance2 = to_center.squared_length();
+        if distance2 <= radius * radius {
+            //from inside any point is visible, so pick one uniformly over the area
+            return Some(&self.center + &(&uniform_sphere(sampler) * radius));
+        }
+        let cos_max = (1.0 - radius * radius / distance2).max(0.0).sqrt();
+        let cos_theta = 1.0 - sampler.gen() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * consts::PI * sampler.gen();
+        let frame = Onb::from_normal(&to_center.normalize());
+        let direction = frame.to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        //the nearer root of the ray meeting the sphere, clamped for rays grazing its rim
+        let along = direction.dot(&to_center);
+        let half_chord = (radius * radius - (distance2 - along * along))
+            .max(0.0)
+            .sqrt();
+        Some(origin + &(&direction * (along - half_chord)))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        let radius = self.radius.abs();
+        let distance2 = (&self.center - ray.origin()).squared_length();
+        if distance2 <= radius * radius {
+            return area_pdf(ray, hit, &hit.normal, 4.0 * consts::PI * radius * radius);
         }
+        let cos_max = (1.0 - radius * radius / distance2).max(0.0).sqrt();
+        1.0 / (2.0 * consts::PI * (1.0 - cos_max))
+    }
+}
+
+//uniform over the unit sphere
+fn uniform_sphere(sampler: &mut Sampler) -> Vec3 {
+    let z = sampler.gen_range(-1.0, 1.0);
+    let phi = sampler.gen_range(0.0, 2.0 * consts::PI);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+//the density over solid angle of hit's point when points are picked uniformly over the area of
+//a surface with this normal there, lights shine from both sides
+fn area_pdf(ray: &Ray, hit: &Hit, normal: &Vec3, area: f32) -> f32 {
+    let cosine = normal.dot(ray.direction()).abs();
+    if cosine <= 0.0 || area <= 0.0 {
+        return 0.0;
     }
+    hit.distance * hit.distance / (cosine * area)
 }
 
-impl<'a> Normal for Sphere<'a> {
+impl Normal for Sphere {
     fn normal(&self, point: &Vec3) -> Ray {
         Ray::new(point, &(&(point - &self.center) * self.radius.signum()))
     }
     fn material(&self) -> &dyn Material {
-        self.material
+        &*self.material
+    }
+}
+
+/// a sphere whose center moves in a straight line from center0 at time0 to center1 at time1
+#[derive(Debug)]
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: &Vec3,
+        center1: &Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        MovingSphere {
+            center0: *center0,
+            center1: *center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+    /// held at the end positions outside [time0, time1]
+    pub fn center(&self, time: f32) -> Vec3 {
+        let t = if self.time1 > self.time0 {
+            ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        &(&self.center0 * (1.0 - t)) + &(&self.center1 * t)
+    }
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Intersect for MovingSphere {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let center = self.center(ray.time());
+        let oc = ray.origin() - &center;
+        let half_b = ray.direction().dot(&oc);
+        let c = oc.squared_length() - self.radius * self.radius;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let t = if -half_b - root >= t_min && -half_b - root < t_max {
+            -half_b - root
+        } else if -half_b + root > t_min && -half_b + root < t_max {
+            -half_b + root
+        } else {
+            return None;
+        };
+        let point = ray.parameterization(t);
+        let normal = &(&point - &center) * self.radius.signum();
+        let (u, v) = sphere_uv(&center, self.radius, &point);
+        Some(
+            Hit::new(&point, t, self)
+                .with_uv(u, v)
+                .with_normal(&normal.normalize())
+                .with_tangents(&sphere_tangent(&center, &point), &Vec3::new(0.0, 1.0, 0.0)),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        //the box around the sphere at both ends covers every position in between
+        let r = Vec3::from_float(self.radius.abs());
+        let start = Aabb::new(&(&self.center0 - &r), &(&self.center0 + &r));
+        let end = Aabb::new(&(&self.center1 - &r), &(&self.center1 + &r));
+        Some(start.union(&end))
+    }
+}
+
+impl Normal for MovingSphere {
+    /// the normal with the sphere at time0, hits carry the normal at the time of their ray
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &(&(point - &self.center0) * self.radius.signum()))
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.material
+    }
+}
+
+#[derive(Debug)]
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    //per vertex normals for smooth shading, otherwise the face normal is used
+    normals: Option<[Vec3; 3]>,
+    //per vertex uvs, otherwise the barycentric coordinates are used
+    texcoords: Option<[(f32, f32); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Vec3; 3], material: Arc<dyn Material>) -> Self {
+        Triangle {
+            vertices,
+            normals: None,
+            texcoords: None,
+            material,
+        }
+    }
+    pub fn smooth(vertices: [Vec3; 3], normals: [Vec3; 3], material: Arc<dyn Material>) -> Self {
+        Triangle {
+            vertices,
+            normals: Some([
+                normals[0].normalize(),
+                normals[1].normalize(),
+                normals[2].normalize(),
+            ]),
+            texcoords: None,
+            material,
+        }
+    }
+    pub fn with_texcoords(mut self, texcoords: [(f32, f32); 3]) -> Self {
+        self.texcoords = Some(texcoords);
+        self
+    }
+    pub fn vertices(&self) -> &[Vec3; 3] {
+        &self.vertices
+    }
+    pub fn area(&self) -> f32 {
+        let e1 = &self.vertices[1] - &self.vertices[0];
+        let e2 = &self.vertices[2] - &self.vertices[0];
+        0.5 * e1.cross(&e2).length()
+    }
+    pub fn face_normal(&self) -> Vec3 {
+        //counter-clockwise winding faces the normal towards the viewer
+        let e1 = &self.vertices[1] - &self.vertices[0];
+        let e2 = &self.vertices[2] - &self.vertices[0];
+        e1.cross(&e2).normalize()
+    }
+    pub fn barycentric(&self, point: &Vec3) -> (f32, f32) {
+        let e1 = &self.vertices[1] - &self.vertices[0];
+        let e2 = &self.vertices[2] - &self.vertices[0];
+        let p = point - &self.vertices[0];
+        let d11 = e1.dot(&e1);
+        let d12 = e1.dot(&e2);
+        let d22 = e2.dot(&e2);
+        let dp1 = p.dot(&e1);
+        let dp2 = p.dot(&e2);
+        let denom = d11 * d22 - d12 * d12;
+        let u = (d22 * dp1 - d12 * dp2) / denom;
+        let v = (d11 * dp2 - d12 * dp1) / denom;
+        (u, v)
+    }
+}
+
+impl Intersect for Triangle {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        //möller-trumbore: solve origin + t*direction = a + u*(b - a) + v*(c - a) with cramer's rule
+        let e1 = &self.vertices[1] - &self.vertices[0];
+        let e2 = &self.vertices[2] - &self.vertices[0];
+        let p = ray.direction().cross(&e2);
+        let det = e1.dot(&p);
+        //the ray is parallel to the plane of the triangle, relative to its area so tiny triangles
+        //are not culled
+        if det.abs() <= 1e-7 * e1.cross(&e2).length() {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = ray.origin() - &self.vertices[0];
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let v = ray.direction().dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&q) * inv_det;
+        if t < t_min || t >= t_max {
+            return None;
+        }
+        let hit = Hit::new(&ray.parameterization(t), t, self).with_barycentric(u, v);
+        Some(match &self.texcoords {
+            Some([a, b, c]) => {
+                //solve e1 = du1*dpdu + dv1*dpdv and e2 = du2*dpdu + dv2*dpdv
+                let (du1, dv1) = (b.0 - a.0, b.1 - a.1);
+                let (du2, dv2) = (c.0 - a.0, c.1 - a.1);
+                let det = du1 * dv2 - du2 * dv1;
+                let hit = hit.with_uv(
+                    a.0 * (1.0 - u - v) + b.0 * u + c.0 * v,
+                    a.1 * (1.0 - u - v) + b.1 * u + c.1 * v,
+                );
+                if det.abs() < 1e-12 {
+                    hit.with_tangents(&e1, &e2)
+                } else {
+                    hit.with_tangents(
+                        &(&(&(&e1 * dv2) - &(&e2 * dv1)) / det),
+                        &(&(&(&e2 * du1) - &(&e1 * du2)) / det),
+                    )
+                }
+            }
+            None => hit.with_uv(u, v).with_tangents(&e1, &e2),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(&self.vertices[0], &self.vertices[1]).grow(&self.vertices[2]))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.emits()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        //folding the unit square along its diagonal covers the triangle evenly
+        let (mut u, mut v) = (sampler.gen(), sampler.gen());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        let [a, b, c] = &self.vertices;
+        Some(&(a + &(&(b - a) * u)) + &(&(c - a) * v))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        area_pdf(ray, hit, &self.face_normal(), self.area())
+    }
+}
+
+impl Normal for Triangle {
+    fn normal(&self, point: &Vec3) -> Ray {
+        match &self.normals {
+            Some(normals) => {
+                let (u, v) = self.barycentric(point);
+                let normal =
+                    &(&(&normals[0] * (1.0 - u - v)) + &(&normals[1] * u)) + &(&normals[2] * v);
+                Ray::new(point, &normal)
+            }
+            None => Ray::new(point, &self.face_normal()),
+        }
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.material
+    }
+}
+
+//flat shapes have no thickness along their normal, pad their boxes so the slab test still sees them
+const FLAT_PADDING: f32 = 1e-4;
+
+fn pad(bbox: Aabb) -> Aabb {
+    let padding = Vec3::from_float(FLAT_PADDING);
+    Aabb::new(&(bbox.min() - &padding), &(bbox.max() + &padding))
+}
+
+/// an infinite plane through point, textures repeat every unit along it
+#[derive(Debug)]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    //orthonormal directions in the plane used for uvs
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: &Vec3, normal: &Vec3, material: Arc<dyn Material>) -> Self {
+        let normal = normal.normalize();
+        //any direction not parallel to the normal gives a tangent
+        let helper = if normal.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(&normal).normalize();
+        let bitangent = normal.cross(&tangent);
+        Plane {
+            point: *point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+    pub fn uv(&self, point: &Vec3) -> (f32, f32) {
+        let p = point - &self.point;
+        (
+            p.dot(&self.tangent).rem_euclid(1.0),
+            p.dot(&self.bitangent).rem_euclid(1.0),
+        )
+    }
+}
+
+impl Intersect for Plane {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let denom = self.normal.dot(ray.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(&self.point - ray.origin())) / denom;
+        if t < t_min || t >= t_max {
+            return None;
+        }
+        let point = ray.parameterization(t);
+        let (u, v) = self.uv(&point);
+        Some(
+            Hit::new(&point, t, self)
+                .with_uv(u, v)
+                .with_tangents(&self.tangent, &self.bitangent),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+impl Normal for Plane {
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &self.normal)
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.material
+    }
+}
+
+//a rectangle in the plane where the `axis` coordinate is k, shared by the three axis aligned rects
+#[derive(Debug)]
+struct AxisRect {
+    //the two in-plane axes and the axis of the normal
+    a: usize,
+    b: usize,
+    axis: usize,
+    a0: f32,
+    a1: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    material: Arc<dyn Material>,
+}
+
+impl AxisRect {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        (a, b, axis): (usize, usize, usize),
+        a0: f32,
+        a1: f32,
+        b0: f32,
+        b1: f32,
+        k: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        AxisRect {
+            a,
+            b,
+            axis,
+            a0: a0.min(a1),
+            a1: a0.max(a1),
+            b0: b0.min(b1),
+            b1: b0.max(b1),
+            k,
+            material,
+        }
+    }
+
+    fn point(&self, a: f32, b: f32, k: f32) -> Vec3 {
+        let mut p = [0.0; 3];
+        p[self.a] = a;
+        p[self.b] = b;
+        p[self.axis] = k;
+        Vec3::new(p[0], p[1], p[2])
+    }
+
+    fn intersect<'a>(
+        &self,
+        object: &'a dyn Normal,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Hit<'a>> {
+        let t = (self.k - ray.origin()[self.axis]) / ray.direction()[self.axis];
+        //also rejects the NaN of a ray parallel to the rect
+        if !(t >= t_min && t < t_max) {
+            return None;
+        }
+        let point = ray.parameterization(t);
+        let (a, b) = (point[self.a], point[self.b]);
+        if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
+            return None;
+        }
+        let u = (a - self.a0) / (self.a1 - self.a0);
+        let v = (b - self.b0) / (self.b1 - self.b0);
+        Some(
+            Hit::new(&point, t, object)
+                .with_uv(u, v)
+                .with_tangents(&self.point(1.0, 0.0, 0.0), &self.point(0.0, 1.0, 0.0)),
+        )
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        pad(Aabb::new(
+            &self.point(self.a0, self.b0, self.k),
+            &self.point(self.a1, self.b1, self.k),
+        ))
+    }
+
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &self.point(0.0, 0.0, 1.0))
+    }
+
+    fn sample_point(&self, sampler: &mut Sampler) -> Vec3 {
+        self.point(
+            sampler.gen_range(self.a0, self.a1),
+            sampler.gen_range(self.b0, self.b1),
+            self.k,
+        )
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+        area_pdf(ray, hit, &self.point(0.0, 0.0, 1.0), area)
+    }
+}
+
+/// a rectangle at z = k facing +z
+#[derive(Debug)]
+pub struct XYRect(AxisRect);
+
+impl XYRect {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        XYRect(AxisRect::new((0, 1, 2), x0, x1, y0, y1, k, material))
+    }
+}
+
+/// a rectangle at y = k facing +y
+#[derive(Debug)]
+pub struct XZRect(AxisRect);
+
+impl XZRect {
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        XZRect(AxisRect::new((0, 2, 1), x0, x1, z0, z1, k, material))
+    }
+}
+
+/// a rectangle at x = k facing +x
+#[derive(Debug)]
+pub struct YZRect(AxisRect);
+
+impl YZRect {
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        YZRect(AxisRect::new((1, 2, 0), y0, y1, z0, z1, k, material))
+    }
+}
+
+impl Intersect for XYRect {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        self.0.intersect(self, ray, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.0.bounding_box())
+    }
+    fn is_light(&self) -> bool {
+        self.0.material.emits()
+    }
+    fn sample_point(&self, _origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        Some(self.0.sample_point(sampler))
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        self.0.pdf(ray, hit)
+    }
+}
+
+impl Normal for XYRect {
+    fn normal(&self, point: &Vec3) -> Ray {
+        self.0.normal(point)
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.0.material
+    }
+}
+
+impl Intersect for XZRect {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        self.0.intersect(self, ray, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.0.bounding_box())
+    }
+    fn is_light(&self) -> bool {
+        self.0.material.emits()
+    }
+    fn sample_point(&self, _origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        Some(self.0.sample_point(sampler))
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        self.0.pdf(ray, hit)
+    }
+}
+
+impl Normal for XZRect {
+    fn normal(&self, point: &Vec3) -> Ray {
+        self.0.normal(point)
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.0.material
+    }
+}
+
+impl Intersect for YZRect {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        self.0.intersect(self, ray, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.0.bounding_box())
+    }
+    fn is_light(&self) -> bool {
+        self.0.material.emits()
+    }
+    fn sample_point(&self, _origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        Some(self.0.sample_point(sampler))
+    }
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        self.0.pdf(ray, hit)
+    }
+}
+
+impl Normal for YZRect {
+    fn normal(&self, point: &Vec3) -> Ray {
+        self.0.normal(point)
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.0.material
+    }
+}
+
+/// the parallelogram corner + s*u + t*v for s and t in [0, 1], facing u x v
+#[derive(Debug)]
+pub struct Quad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    //scaled normal that turns a point in the plane into its (s, t) coordinates
+    w: Vec3,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(corner: &Vec3, u: &Vec3, v: &Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        Quad {
+            corner: *corner,
+            u: *u,
+            v: *v,
+            normal: n.normalize(),
+            w: &n / n.dot(&n),
+            material,
+        }
+    }
+    pub fn corner(&self) -> &Vec3 {
+        &self.corner
+    }
+    pub fn u(&self) -> &Vec3 {
+        &self.u
+    }
+    pub fn v(&self) -> &Vec3 {
+        &self.v
+    }
+    pub fn area(&self) -> f32 {
+        self.u.cross(&self.v).length()
+    }
+}
+
+impl Intersect for Quad {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        _sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let denom = self.normal.dot(ray.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(&self.corner - ray.origin())) / denom;
+        if t < t_min || t >= t_max {
+            return None;
+        }
+        let point = ray.parameterization(t);
+        let p = &point - &self.corner;
+        let alpha = self.w.dot(&p.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&p));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(
+            Hit::new(&point, t, self)
+                .with_uv(alpha, beta)
+                .with_tangents(&self.u, &self.v),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let far = &(&self.corner + &self.u) + &self.v;
+        let bbox = Aabb::new(&self.corner, &far)
+            .grow(&(&self.corner + &self.u))
+            .grow(&(&self.corner + &self.v));
+        Some(pad(bbox))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.emits()
+    }
+
+    fn sample_point(&self, _origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let (s, t) = (sampler.gen(), sampler.gen());
+        Some(&(&self.corner + &(&self.u * s)) + &(&self.v * t))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        area_pdf(ray, hit, &self.normal, self.area())
+    }
+}
+
+impl Normal for Quad {
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &self.normal)
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.material
+    }
+}
+
+/// an axis aligned box made of six outward facing quads
+#[derive(Debug)]
+pub struct BoxShape {
+    min: Vec3,
+    max: Vec3,
+    sides: Vec<Quad>,
+}
+
+impl BoxShape {
+    pub fn new(a: &Vec3, b: &Vec3, material: Arc<dyn Material>) -> Self {
+        let min = a.min(b);
+        let max = a.max(b);
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+        let side = |corner: Vec3, u: &Vec3, v: &Vec3| Quad::new(&corner, u, v, material.clone());
+        let sides = vec![
+            //front, right, back, left, top, bottom
+            side(Vec3::new(min.x(), min.y(), max.z()), &dx, &dy),
+            side(Vec3::new(max.x(), min.y(), max.z()), &-dz, &dy),
+            side(Vec3::new(max.x(), min.y(), min.z()), &-dx, &dy),
+            side(min, &dz, &dy),
+            side(Vec3::new(min.x(), max.y(), max.z()), &dx, &-dz),
+            side(min, &dx, &dz),
+        ];
+        BoxShape { min, max, sides }
+    }
+    pub fn sides(&self) -> &[Quad] {
+        &self.sides
+    }
+}
+
+impl Intersect for BoxShape {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let mut closest: Option<Hit> = None;
+        for side in &self.sides {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.distance);
+            if let Some(hit) = side.intersect(ray, t_min, t_max, sampler) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(&self.min, &self.max))
+    }
+
+    fn is_light(&self) -> bool {
+        self.sides[0].is_light()
+    }
+
+    //uniform over the whole surface, points on the far sides are simply shadowed
+    fn sample_point(&self, origin: &Vec3, sampler: &mut Sampler) -> Option<Vec3> {
+        let total: f32 = self.sides.iter().map(|side| side.area()).sum();
+        let mut pick = sampler.gen() * total;
+        for side in &self.sides {
+            pick -= side.area();
+            if pick < 0.0 {
+                return side.sample_point(origin, sampler);
+            }
+        }
+        self.sides[5].sample_point(origin, sampler)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        let total: f32 = self.sides.iter().map(|side| side.area()).sum();
+        area_pdf(ray, hit, &hit.normal, total)
+    }
+}
+
+impl Normal for BoxShape {
+    fn normal(&self, point: &Vec3) -> Ray {
+        //the side the point lies on is the closest of the six planes
+        let mut best = (f32::INFINITY, 0, 0.0);
+        for axis in 0..3 {
+            let to_min = (point[axis] - self.min[axis]).abs();
+            let to_max = (self.max[axis] - point[axis]).abs();
+            if to_min < best.0 {
+                best = (to_min, axis, -1.0);
+            }
+            if to_max < best.0 {
+                best = (to_max, axis, 1.0);
+            }
+        }
+        let mut normal = [0.0; 3];
+        normal[best.1] = best.2;
+        Ray::new(point, &Vec3::new(normal[0], normal[1], normal[2]))
+    }
+    fn material(&self) -> &dyn Material {
+        self.sides[0].material()
+    }
+}
+
+/// places a shared object in the world through a transform, many instances can share one mesh
+#[derive(Debug, Clone)]
+pub struct Instance {
+    object: Arc<dyn Intersect>,
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Intersect>, transform: Transform) -> Self {
+        Instance { object, transform }
+    }
+    pub fn object(&self) -> &Arc<dyn Intersect> {
+        &self.object
+    }
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl Intersect for Instance {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        intersect_transformed(&*self.object, &self.transform, ray, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.transform.bounding_box(&self.object.bounding_box()?))
+    }
+}
+
+/// an instance whose transform is animated over the shutter, for objects that move or spin
+#[derive(Debug, Clone)]
+pub struct MovingInstance {
+    object: Arc<dyn Intersect>,
+    motion: AnimatedTransform,
+}
+
+impl MovingInstance {
+    pub fn new(object: Arc<dyn Intersect>, motion: AnimatedTransform) -> Self {
+        MovingInstance { object, motion }
+    }
+    pub fn object(&self) -> &Arc<dyn Intersect> {
+        &self.object
+    }
+    pub fn motion(&self) -> &AnimatedTransform {
+        &self.motion
+    }
+}
+
+impl Intersect for MovingInstance {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let transform = self.motion.at(ray.time())?;
+        intersect_transformed(&*self.object, &transform, ray, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.motion.bounding_box(&self.object.bounding_box()?))
+    }
+}
+
+fn intersect_transformed<'a>(
+    object: &'a dyn Intersect,
+    transform: &Transform,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut Sampler,
+) -> Option<Hit<'a>> {
+    let to_object = transform.inverse();
+    let direction = to_object.vector(ray.direction());
+    //object space rays are renormalized so distances along them are stretched by this much
+    let stretch = direction.length();
+    let local = Ray::at_time(&to_object.point(ray.origin()), &direction, ray.time());
+    let hit = object.intersect(&local, t_min * stretch, t_max * stretch, sampler)?;
+    let frame = hit.shading;
+    Some(
+        Hit {
+            point: transform.point(&hit.point),
+            distance: hit.distance / stretch,
+            ..hit
+        }
+        .with_normal(&transform.normal(&hit.normal))
+        .with_tangents(
+            &transform.vector(&frame.tangent),
+            &transform.vector(&frame.bitangent),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sphere_uv() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let sphere = Sphere::new(&Vec3::new(1.0, 1.0, 1.0), 2.0, material);
+        let (u, v) = sphere.uv(&Vec3::new(1.0, 1.0, 3.0));
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+        let (_, v) = sphere.uv(&Vec3::new(1.0, 3.0, 1.0));
+        assert!((v - 1.0).abs() < 1e-6);
+        let ray = Ray::new(&Vec3::new(1.0, 1.0, 10.0), &Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.uv.0 - 0.25).abs() < 1e-6 && (hit.uv.1 - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_triangle_barycentric() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let triangle = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            material,
+        );
+        let ray = Ray::new(&Vec3::new(0.25, 0.5, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+        let hit = triangle
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 1.0).abs() < 1e-6);
+        let (u, v) = hit.barycentric.unwrap();
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+        let (bu, bv) = triangle.barycentric(&hit.point);
+        assert!((bu - u).abs() < 1e-6 && (bv - v).abs() < 1e-6);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let miss = Ray::new(&Vec3::new(0.75, 0.5, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle
+            .intersect(&miss, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+    }
+
+    #[test]
+    fn test_tiny_triangle() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let triangle = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1e-4, 0.0, 0.0),
+                Vec3::new(0.0, 1e-4, 0.0),
+            ],
+            material,
+        );
+        let ray = Ray::new(&Vec3::new(2.5e-5, 5e-5, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+        let hit = triangle
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 1.0).abs() < 1e-6);
+        let (u, v) = hit.barycentric.unwrap();
+        assert!((u - 0.25).abs() < 1e-3 && (v - 0.5).abs() < 1e-3);
+        let miss = Ray::new(&Vec3::new(7.5e-5, 5e-5, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle
+            .intersect(&miss, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+    }
+
+    #[test]
+    fn test_triangle_smooth_normal() {
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let tilted = Vec3::new(1.0, 0.0, 1.0);
+        let triangle = Triangle::smooth(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            [up, tilted, up],
+            material,
+        );
+        let at_vertex = triangle.normal(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((at_vertex.direction() - &tilted.normalize()).length() < 1e-6);
+        let at_corner = triangle.normal(&Vec3::new(0.0, 0.0, 0.0));
+        assert!((at_corner.direction() - &up).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_plane() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let plane = Plane::new(
+            &Vec3::new(0.0, -1.0, 0.0),
+            &Vec3::new(0.0, 2.0, 0.0),
+            material,
+        );
+        assert!(plane.bounding_box().is_none());
+        let ray = Ray::new(&Vec3::new(3.25, 1.0, -7.5), &Vec3::new(0.0, -1.0, 0.0));
+        let hit = plane
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 2.0).abs() < 1e-6);
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((0.0..1.0).contains(&hit.uv.0) && (0.0..1.0).contains(&hit.uv.1));
+        let parallel = Ray::new(&Vec3::new(0.0, 1.0, 0.0), &Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane
+            .intersect(&parallel, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+    }
+
+    #[test]
+    fn test_rects_and_quads() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let rect = XZRect::new(0.0, 2.0, 0.0, 4.0, 1.0, material.clone());
+        let quad = Quad::new(
+            &Vec3::new(0.0, 1.0, 0.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, 4.0),
+            material,
+        );
+        let ray = Ray::new(&Vec3::new(0.5, 3.0, 3.0), &Vec3::new(0.0, -1.0, 0.0));
+        let rect_hit = rect
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let quad_hit = quad
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        for hit in [&rect_hit, &quad_hit].iter() {
+            assert!((hit.distance - 2.0).abs() < 1e-6);
+            assert!((hit.uv.0 - 0.25).abs() < 1e-6 && (hit.uv.1 - 0.75).abs() < 1e-6);
+            assert_eq!(hit.normal.y().abs(), 1.0);
+        }
+        assert_eq!(rect_hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        //x cross z points down
+        assert_eq!(quad_hit.normal, Vec3::new(0.0, -1.0, 0.0));
+        let outside = Ray::new(&Vec3::new(2.5, 3.0, 3.0), &Vec3::new(0.0, -1.0, 0.0));
+        assert!(rect
+            .intersect(&outside, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        assert!(quad
+            .intersect(&outside, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        assert!(rect.bounding_box().unwrap().hit(&ray, 0.001, f32::INFINITY));
+        assert!(quad.bounding_box().unwrap().hit(&ray, 0.001, f32::INFINITY));
+    }
+
+    #[test]
+    fn test_box_faces_outwards() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let cube = BoxShape::new(&Vec3::from_float(1.0), &Vec3::from_float(-1.0), material);
+        let center = Vec3::from_float(0.0);
+        for axis in 0..3 {
+            for &sign in [-1.0, 1.0].iter() {
+                let mut d = [0.0; 3];
+                d[axis] = sign;
+                let direction = Vec3::new(d[0], d[1], d[2]);
+                let ray = Ray::new(&(&direction * 5.0), &-direction);
+                let hit = cube
+                    .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+                    .unwrap();
+                assert!((hit.distance - 4.0).abs() < 1e-5);
+                assert_eq!(hit.normal, direction);
+                assert_eq!(*cube.normal(&hit.point).direction(), direction);
+                //from the inside the far side is hit
+                let inside = Ray::new(&center, &direction);
+                let hit = cube
+                    .intersect(&inside, 0.001, f32::INFINITY, &mut sampler)
+                    .unwrap();
+                assert!((hit.distance - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_instance() {
+        let mut sampler = Sampler::from_seed(0);
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let cube: Arc<dyn Intersect> = Arc::new(BoxShape::new(
+            &Vec3::from_float(-0.5),
+            &Vec3::from_float(0.5),
+            material,
+        ));
+        //a 1x4x1 pillar turned 45 degrees and stood on the ground at x = 10
+        let transform = Transform::identity()
+            .scale(&Vec3::new(1.0, 4.0, 1.0))
+            .rotate_y(45.0)
+            .translate(&Vec3::new(10.0, 2.0, 0.0));
+        let pillar = Instance::new(cube.clone(), transform);
+        let bbox = pillar.bounding_box().unwrap();
+        assert!((bbox.max().y() - 4.0).abs() < 1e-5 && bbox.min().y().abs() < 1e-5);
+        assert!((bbox.max().x() - (10.0 + 0.5f32.sqrt())).abs() < 1e-5);
+
+        let ray = Ray::new(&Vec3::new(0.0, 3.5, 0.0), &Vec3::new(1.0, 0.0, 0.0));
+        let hit = pillar
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        //the corner of the turned cube points back along the ray
+        let corner = 10.0 - 0.5f32.sqrt();
+        assert!((hit.distance - corner).abs() < 1e-4);
+        assert!((&hit.point - &Vec3::new(corner, 3.5, 0.0)).length() < 1e-4);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-5 && hit.normal.x() < 0.0);
+        assert!((hit.normal.z().abs() - 0.5f32.sqrt()).abs() < 1e-4);
+        //t_max is in world units
+        assert!(pillar
+            .intersect(&ray, 0.001, corner - 0.01, &mut sampler)
+            .is_none());
+        let above = Ray::new(&Vec3::new(0.0, 4.5, 0.0), &Vec3::new(1.0, 0.0, 0.0));
+        assert!(pillar
+            .intersect(&above, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        //the original is untouched and shared
+        assert!(cube
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        assert_eq!(Arc::strong_count(&cube), 2);
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let lamp: Arc<dyn Material> = Arc::new(DiffuseLight::new(&Vec3::from_float(1.0)));
+        let origin = Vec3::new(0.3, -0.2, 0.1);
+        let shapes: Vec<Box<dyn Intersect>> = vec![
+            Box::new(Sphere::new(&Vec3::new(0.5, 2.0, 0.0), 0.7, lamp.clone())),
+            Box::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 1.0, lamp.clone())),
+            Box::new(Quad::new(
+                &Vec3::new(-1.0, 1.0, -0.5),
+                &Vec3::new(2.0, 0.0, 0.0),
+                &Vec3::new(0.0, 0.5, -1.0),
+                lamp.clone(),
+            )),
+            Box::new(Triangle::new(
+                [
+                    Vec3::new(-1.0, 1.0, 0.0),
+                    Vec3::new(1.0, 1.5, 0.0),
+                    Vec3::new(0.0, 1.0, -2.0),
+                ],
+                lamp.clone(),
+            )),
+            Box::new(XZRect::new(-1.0, 1.0, -1.0, 0.5, 1.2, lamp.clone())),
+            Box::new(BoxShape::new(
+                &Vec3::new(1.0, 0.5, -1.0),
+                &Vec3::new(2.0, 1.5, 0.5),
+                lamp,
+            )),
+        ];
+        let mut sampler = Sampler::from_seed(4);
+        let n = 100_000;
+        for shape in &shapes {
+            assert!(shape.is_light());
+            //the solid angle the shape covers, from the fraction of random directions that hit it
+            let hits = (0..n)
+                .filter(|_| {
+                    let ray = Ray::new(&origin, &uniform_sphere(&mut sampler));
+                    shape
+                        .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+                        .is_some()
+                })
+                .count();
+            let covered = 4.0 * consts::PI * hits as f32 / n as f32;
+            //each visible sample stands for 1 / pdf of solid angle
+            let mut estimate = 0.0;
+            for _ in 0..n {
+                let point = shape.sample_point(&origin, &mut sampler).unwrap();
+                let ray = Ray::new(&origin, &(&point - &origin));
+                let hit = shape
+                    .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+                    .unwrap();
+                if (hit.distance - (&point - &origin).length()).abs() < 1e-3 {
+                    estimate += 1.0 / shape.pdf(&ray, &hit) / n as f32;
+                }
+            }
+            assert!(
+                (estimate - covered).abs() < 0.03 * covered,
+                "{:?} {} {}",
+                shape,
+                estimate,
+                covered
+            );
+        }
+        let plain = Sphere::new(
+            &origin,
+            1.0,
+            Arc::new(Lambertian::new(&Vec3::from_float(0.5))),
+        );
+        assert!(!plain.is_light());
+    }
+
+    #[test]
+    fn test_shading_frame() {
+        let mut sampler = Sampler::from_seed(0);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let close = |a: &Vec3, b: &Vec3| (a - b).length() < 1e-5;
+        let down = Ray::new(&Vec3::new(0.5, 1.0, -0.5), &Vec3::new(0.0, -1.0, 0.0));
+        let quad = Quad::new(
+            &Vec3::from_float(0.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, -1.0),
+            material.clone(),
+        );
+        let hit = quad
+            .intersect(&down, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert_eq!(hit.shading.normal, hit.normal);
+        assert!(close(&hit.shading.tangent, &Vec3::new(1.0, 0.0, 0.0)));
+        assert!(close(&hit.shading.bitangent, &Vec3::new(0.0, 0.0, -1.0)));
+
+        //u runs against x here so the frame is mirrored to keep the bitangent along v
+        let triangle = Triangle::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            material.clone(),
+        )
+        .with_texcoords([(1.0, 0.0), (0.0, 0.0), (1.0, 1.0)]);
+        let ray = Ray::new(&Vec3::new(0.25, 0.25, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+        let hit = triangle
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!(close(&hit.shading.tangent, &Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(close(&hit.shading.bitangent, &Vec3::new(0.0, 1.0, 0.0)));
+
+        let sphere = Sphere::new(&Vec3::from_float(0.0), 1.0, material);
+        let hit = sphere
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!(hit.shading.tangent.dot(&hit.normal).abs() < 1e-5);
+        assert!(hit.shading.tangent.x() > 0.9 && hit.shading.bitangent.y() > 0.9);
+
+        //instances carry the frame through their transform
+        let transform = Transform::identity().rotate_y(90.0);
+        let turned = Instance::new(Arc::new(quad), transform);
+        let ray = Ray::new(&transform.point(down.origin()), down.direction());
+        let hit = turned
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        let tangent = transform.vector(&Vec3::new(1.0, 0.0, 0.0));
+        assert!(close(&hit.shading.tangent, &tangent));
+        assert!(close(&hit.shading.normal, &hit.normal));
+    }
+
+    #[test]
+    fn test_motion() {
+        let mut sampler = Sampler::from_seed(0);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        //moves from x = 0 to x = 4 between times 0 and 1
+        let sphere = MovingSphere::new(
+            &Vec3::new(0.0, 0.0, 0.0),
+            &Vec3::new(4.0, 0.0, 0.0),
+            0.0,
+            1.0,
+            1.0,
+            material.clone(),
+        );
+        let bbox = sphere.bounding_box().unwrap();
+        assert_eq!((bbox.min().x(), bbox.max().x()), (-1.0, 5.0));
+        let down = |x: f32, time: f32| {
+            Ray::at_time(&Vec3::new(x, 5.0, 0.0), &Vec3::new(0.0, -1.0, 0.0), time)
+        };
+        assert!(sphere
+            .intersect(&down(3.0, 0.0), 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        let hit = sphere
+            .intersect(&down(3.0, 0.75), 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-5);
+        assert!((&hit.normal - &Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+
+        let unit: Arc<dyn Intersect> =
+            Arc::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 1.0, material));
+        let motion = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::identity().translate(&Vec3::new(4.0, 0.0, 0.0)),
+            0.0,
+            1.0,
+        );
+        let instance = MovingInstance::new(unit, motion);
+        assert!(instance
+            .intersect(&down(3.0, 0.0), 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+        let hit = instance
+            .intersect(&down(3.0, 0.75), 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-4);
+        //scattered rays keep the time of the ray that hit
+        let mut sampler = Sampler::new(0, 0, 0);
+        let scattered = hit.scatter(&down(3.0, 0.75), &mut sampler).unwrap();
+        assert_eq!(scattered.ray.time(), 0.75);
+    }
+
+    #[test]
+    fn test_rotating_instance() {
+        let mut sampler = Sampler::from_seed(0);
+        //a box twice as long along x as it is deep, turning a quarter round the y axis
+        let material = Arc::new(Lambertian::new(&Vec3::from_float(0.5)));
+        let plank: Arc<dyn Intersect> = Arc::new(BoxShape::new(
+            &Vec3::new(-1.0, -0.5, -0.5),
+            &Vec3::new(1.0, 0.5, 0.5),
+            material,
+        ));
+        let motion = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::identity().rotate_y(90.0),
+            0.0,
+            1.0,
+        );
+        let instance = MovingInstance::new(plank, motion);
+        //halfway it lies along the diagonal at full length, an entry by entry blend shrinks it
+        let diagonal = Vec3::new(1.0, 0.0, -1.0).normalize();
+        let inward = Ray::at_time(&(&diagonal * 5.0), &-diagonal, 0.5);
+        let hit = instance
+            .intersect(&inward, 0.001, f32::INFINITY, &mut sampler)
+            .unwrap();
+        assert!((hit.distance - 4.0).abs() < 1e-4, "{}", hit.distance);
+        //and its corners sweep past where either end's box reaches
+        let bbox = instance.bounding_box().unwrap();
+        let halfway = instance.motion().at(0.5).unwrap();
+        for corner in 0..8 {
+            let pick =
+                |axis: usize, low: f32, high: f32| if corner >> axis & 1 == 0 { low } else { high };
+            let p = halfway.point(&Vec3::new(
+                pick(0, -1.0, 1.0),
+                pick(1, -0.5, 0.5),
+                pick(2, -0.5, 0.5),
+            ));
+            assert!((0..3).all(|axis| bbox.min()[axis] <= p[axis] && p[axis] <= bbox.max()[axis]));
+        }
     }
 }
@@ -0,0 +1,553 @@
+//! Colors that vary over a surface.
+
+use super::noise::*;
+use super::sampler::*;
+use super::tonemap::*;
+use super::vec3::*;
+use core::fmt::Debug;
+use image::codecs::hdr::HdrDecoder;
+use image::ImageError;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+
+/// a color that varies over a surface
+pub trait Texture: Debug + Send + Sync {
+    /// u and v are the surface coordinates of the hit, both in [0, 1]
+    fn value(&self, u: f32, v: f32, point: &Vec3) -> Vec3;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: &Vec3) -> Self {
+        SolidColor { color: *color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _point: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/// alternates between two textures in a 3d grid so it needs no uv mapping
+#[derive(Debug)]
+pub struct Checker {
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Box<dyn Texture>, odd: Box<dyn Texture>, scale: f32) -> Self {
+        Checker { even, odd, scale }
+    }
+    pub fn from_colors(even: &Vec3, odd: &Vec3, scale: f32) -> Self {
+        Checker::new(
+            Box::new(SolidColor::new(even)),
+            Box::new(SolidColor::new(odd)),
+            scale,
+        )
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, point: &Vec3) -> Vec3 {
+        let sines = (self.scale * point.x()).sin()
+            * (self.scale * point.y()).sin()
+            * (self.scale * point.z()).sin();
+        if sines < 0.0 {
+            self.odd.value(u, v, point)
+        } else {
+            self.even.value(u, v, point)
+        }
+    }
+}
+
+/// how an image is read between the centers of its texels
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// the texel under the point, crisp but blocky up close
+    #[default]
+    Nearest,
+    /// a blend of the four nearest texels
+    Bilinear,
+}
+
+/// what an image shows outside of uv coordinates in [0, 1]
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    /// tiles the image
+    Repeat,
+    /// stretches the edge texels outwards
+    #[default]
+    Clamp,
+    /// tiles the image flipping every other copy so the edges meet seamlessly
+    Mirror,
+}
+
+impl Wrap {
+    fn index(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        (match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.clamp(0, n - 1),
+            Wrap::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        }) as usize
+    }
+}
+
+/// an image of linear colors mapped onto a surface by its uv coordinates
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    //rows from top to bottom
+    texels: Vec<Vec3>,
+    filter: Filter,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    /// texels are linear, rows from top to bottom
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3>) -> Self {
+        assert_eq!(
+            Some(texels.len()),
+            width.checked_mul(height),
+            "texel count must be width * height"
+        );
+        ImageTexture {
+            width,
+            height,
+            texels,
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// png, jpeg and ppm files are taken to be sRGB and converted to linear, hdr files are
+    /// already linear
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        ImageTexture::read(path.as_ref(), srgb_decode)
+    }
+
+    /// loads an image whose values are data rather than colors, such as a normal or bump map, so
+    /// nothing is converted
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        ImageTexture::read(path.as_ref(), |c| c)
+    }
+
+    //decode turns 8 and 16 bit channel values in [0, 1] into linear ones
+    fn read(path: &Path, decode: fn(f32) -> f32) -> io::Result<ImageTexture> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ppm") => ImageTexture::read_ppm(path, decode),
+            Some("hdr") => {
+                let decoder =
+                    HdrDecoder::new(BufReader::new(fs::File::open(path)?)).map_err(image_error)?;
+                let metadata = decoder.metadata();
+                let texels = decoder
+                    .read_image_hdr()
+                    .map_err(image_error)?
+                    .iter()
+                    .map(|texel| Vec3::new(texel[0], texel[1], texel[2]))
+                    .collect();
+                Ok(ImageTexture::new(
+                    metadata.width as usize,
+                    metadata.height as usize,
+                    texels,
+                ))
+            }
+            _ => {
+                let image = image::open(path).map_err(image_error)?;
+                let color = image.color();
+                //16 bit files keep their precision
+                let (width, height, values, max) =
+                    if color.bytes_per_pixel() > color.channel_count() {
+                        let image = image.to_rgb16();
+                        let values: Vec<f32> = image.as_raw().iter().map(|&c| c as f32).collect();
+                        (image.width(), image.height(), values, 65535.0)
+                    } else {
+                        let image = image.to_rgb8();
+                        let values: Vec<f32> = image.as_raw().iter().map(|&c| c as f32).collect();
+                        (image.width(), image.height(), values, 255.0)
+                    };
+                let texels = values
+                    .chunks_exact(3)
+                    .map(|c| {
+                        let channel = |i: usize| decode(c[i] / max);
+                        Vec3::new(channel(0), channel(1), channel(2))
+                    })
+                    .collect();
+                Ok(ImageTexture::new(width as usize, height as usize, texels))
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// reads ascii (P3) and binary (P6) ppm files such as the ones the renderer writes, converting
+    /// them from sRGB to linear
+    pub fn load_ppm<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        ImageTexture::read_ppm(path.as_ref(), srgb_decode)
+    }
+
+    fn read_ppm(path: &Path, decode: fn(f32) -> f32) -> io::Result<ImageTexture> {
+        let data = fs::read(path)?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        //the header is four whitespace separated fields, comments run from # to the end of the line
+        let mut fields = vec![];
+        let mut position = 0;
+        while fields.len() < 4 {
+            while position < data.len() && data[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if position < data.len() && data[position] == b'#' {
+                while position < data.len() && data[position] != b'\n' {
+                    position += 1;
+                }
+                continue;
+            }
+            let start = position;
+            while position < data.len() && !data[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if start == position {
+                return Err(invalid("truncated ppm header"));
+            }
+            fields.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+        }
+        let number = |field: &str| -> io::Result<usize> {
+            field
+                .parse()
+                .map_err(|_| invalid(&format!("invalid ppm header field '{}'", field)))
+        };
+        let width = number(&fields[1])?;
+        let height = number(&fields[2])?;
+        let max = number(&fields[3])?;
+        if max == 0 || max > 65535 {
+            return Err(invalid("ppm maximum value must be in 1..=65535"));
+        }
+        let count = width
+            .checked_mul(height)
+            .and_then(|texels| texels.checked_mul(3))
+            .ok_or_else(|| invalid("ppm is too large"))?;
+        let values: Vec<usize> = match fields[0].as_str() {
+            "P3" => data[position..]
+                .split(|c| c.is_ascii_whitespace())
+                .filter(|field| !field.is_empty())
+                .take(count)
+                .map(|field| number(&String::from_utf8_lossy(field)))
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                //exactly one whitespace character separates the header from the samples
+                let samples = &data[(position + 1).min(data.len())..];
+                if max < 256 {
+                    samples.iter().take(count).map(|&c| c as usize).collect()
+                } else {
+                    samples
+                        .chunks_exact(2)
+                        .take(count)
+                        .map(|c| (c[0] as usize) << 8 | c[1] as usize)
+                        .collect()
+                }
+            }
+            magic => return Err(invalid(&format!("unsupported ppm format '{}'", magic))),
+        };
+        if values.len() < count {
+            return Err(invalid("ppm has fewer samples than its size"));
+        }
+        let texels = values
+            .chunks_exact(3)
+            .map(|c| {
+                let channel = |i: usize| decode(c[i] as f32 / max as f32);
+                Vec3::new(channel(0), channel(1), channel(2))
+            })
+            .collect();
+        Ok(ImageTexture::new(width, height, texels))
+    }
+}
+
+fn image_error(error: ImageError) -> io::Error {
+    match error {
+        ImageError::IoError(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidData, error.to_string()),
+    }
+}
+
+impl ImageTexture {
+    fn texel(&self, i: i64, j: i64) -> Vec3 {
+        let i = self.wrap.index(i, self.width);
+        let j = self.wrap.index(j, self.height);
+        self.texels[j * self.width + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: &Vec3) -> Vec3 {
+        if self.texels.is_empty() {
+            return Vec3::from_float(0.0);
+        }
+        //v runs from the bottom of the image up while rows are stored from the top down
+        let x = u * self.width as f32;
+        let y = (1.0 - v) * self.height as f32;
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                //texel centers sit at half integers
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (i, j) = (x.floor() as i64, y.floor() as i64);
+                let (s, t) = (x - x.floor(), y - y.floor());
+                let top = &(&self.texel(i, j) * (1.0 - s)) + &(&self.texel(i + 1, j) * s);
+                let bottom =
+                    &(&self.texel(i, j + 1) * (1.0 - s)) + &(&self.texel(i + 1, j + 1) * s);
+                &(&top * (1.0 - t)) + &(&bottom * t)
+            }
+        }
+    }
+}
+
+/// the shape a `NoiseTexture` gives its noise
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// a single octave of perlin noise
+    Smooth,
+    /// several octaves summed, for clouds and grime
+    Turbulence,
+    /// veins along z distorted by turbulence
+    Marble,
+    /// rings around the y axis distorted by noise
+    Wood,
+    /// worley cells, dark at the feature points
+    Cells,
+}
+
+/// a solid texture that blends between two colors following a noise pattern in world space
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    pattern: Pattern,
+    perlin: Perlin,
+    worley: Worley,
+    scale: f32,
+    depth: u32,
+    low: Vec3,
+    high: Vec3,
+}
+
+impl NoiseTexture {
+    /// scale is the frequency of the pattern, the sampler seeds the noise
+    pub fn new(
+        pattern: Pattern,
+        scale: f32,
+        low: &Vec3,
+        high: &Vec3,
+        sampler: &mut Sampler,
+    ) -> Self {
+        NoiseTexture {
+            pattern,
+            perlin: Perlin::new(sampler),
+            worley: Worley::new(sampler),
+            scale,
+            depth: 7,
+            low: *low,
+            high: *high,
+        }
+    }
+
+    /// the number of octaves of turbulence, 7 by default
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.perlin = self.perlin.with_smoothing(smoothing);
+        self
+    }
+
+    /// where the point falls between the two colors, in [0, 1]
+    pub fn blend(&self, point: &Vec3) -> f32 {
+        let p = point * self.scale;
+        let t = match self.pattern {
+            Pattern::Smooth => 0.5 * (1.0 + self.perlin.noise(&p)),
+            Pattern::Turbulence => self.perlin.turbulence(&p, self.depth),
+            Pattern::Marble => {
+                0.5 * (1.0 + (p.z() + 10.0 * self.perlin.turbulence(&p, self.depth)).sin())
+            }
+            Pattern::Wood => {
+                let radius = (p.x() * p.x() + p.z() * p.z()).sqrt();
+                let rings = 4.0 * radius + 2.0 * self.perlin.noise(&p);
+                rings - rings.floor()
+            }
+            Pattern::Cells => self.worley.distance(&p),
+        };
+        t.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, point: &Vec3) -> Vec3 {
+        let t = self.blend(point);
+        &(&self.low * (1.0 - t)) + &(&self.high * t)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_image_lookup() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        //top row red, bottom row blue
+        let image = ImageTexture::new(2, 2, vec![red, red, blue, blue]);
+        let origin = Vec3::from_float(0.0);
+        assert_eq!(image.value(0.25, 0.9, &origin), red);
+        assert_eq!(image.value(0.75, 0.1, &origin), blue);
+        assert_eq!(image.value(2.0, -1.0, &origin), blue);
+    }
+
+    #[test]
+    fn test_filter_and_wrap() {
+        let black = Vec3::from_float(0.0);
+        let white = Vec3::from_float(1.0);
+        //one row, black on the left and white on the right
+        let image = ImageTexture::new(2, 1, vec![black, white]);
+        let origin = Vec3::from_float(0.0);
+        let bilinear = image.clone().with_filter(Filter::Bilinear);
+        assert_eq!(bilinear.value(0.25, 0.5, &origin), black);
+        assert_eq!(bilinear.value(0.5, 0.5, &origin), Vec3::from_float(0.5));
+        assert_eq!(bilinear.value(0.75, 0.5, &origin), white);
+        //just past the right edge
+        assert_eq!(image.value(1.25, 0.5, &origin), white);
+        let repeat = image.clone().with_wrap(Wrap::Repeat);
+        assert_eq!(repeat.value(1.25, 0.5, &origin), black);
+        assert_eq!(repeat.value(-0.25, 0.5, &origin), white);
+        let mirror = image.clone().with_wrap(Wrap::Mirror);
+        assert_eq!(mirror.value(1.25, 0.5, &origin), white);
+        assert_eq!(mirror.value(1.75, 0.5, &origin), black);
+        assert_eq!(mirror.value(-0.25, 0.5, &origin), black);
+        //repeating blends across the seam
+        let seam = bilinear.with_wrap(Wrap::Repeat).value(1.0, 0.5, &origin);
+        assert_eq!(seam, Vec3::from_float(0.5));
+    }
+
+    #[test]
+    fn test_load_png_and_hdr() {
+        let directory = std::env::temp_dir();
+        let png = directory.join("texture_test_load.png");
+        image::save_buffer(&png, &[255, 128, 0], 1, 1, image::ColorType::Rgb8).unwrap();
+        let image = ImageTexture::load(&png).unwrap();
+        let data = ImageTexture::load_linear(&png).unwrap();
+        fs::remove_file(&png).unwrap();
+        let texel = image.value(0.5, 0.5, &Vec3::from_float(0.0));
+        assert_eq!(texel.x(), 1.0);
+        assert!((texel.y() - srgb_decode(128.0 / 255.0)).abs() < 1e-6);
+        assert_eq!(texel.z(), 0.0);
+        //maps of data such as normals are read without the sRGB curve
+        let texel = data.value(0.5, 0.5, &Vec3::from_float(0.0));
+        assert_eq!(texel.y(), 128.0 / 255.0);
+
+        let hdr = directory.join("texture_test_load.hdr");
+        let file = fs::File::create(&hdr).unwrap();
+        image::codecs::hdr::HdrEncoder::new(file)
+            .encode(&[image::Rgb([4.0f32, 0.5, 0.25])], 1, 1)
+            .unwrap();
+        let image = ImageTexture::load(&hdr).unwrap();
+        fs::remove_file(&hdr).unwrap();
+        //radiance files store values as is, only rounded to their shared exponent format
+        let texel = image.value(0.5, 0.5, &Vec3::from_float(0.0));
+        assert!((&texel - &Vec3::new(4.0, 0.5, 0.25)).length() < 0.05);
+
+        let missing = ImageTexture::load(directory.join("texture_test_missing.png")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_load_ppm() {
+        let path = std::env::temp_dir().join("texture_test_load_ppm.ppm");
+        fs::write(&path, "P3\n# a comment\n2 1\n255\n255 0 0\n0 0 255\n").unwrap();
+        let image = ImageTexture::load_ppm(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        let origin = Vec3::from_float(0.0);
+        assert_eq!(image.value(0.0, 0.5, &origin), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.value(1.0, 0.5, &origin), Vec3::new(0.0, 0.0, 1.0));
+        //a header whose size overflows is rejected rather than wrapping around
+        let path = std::env::temp_dir().join("texture_test_load_ppm_huge.ppm");
+        fs::write(&path, format!("P6 {} {} 255\n", usize::MAX, 2)).unwrap();
+        let error = ImageTexture::load_ppm(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("too large"), "{}", error);
+    }
+
+    #[test]
+    fn test_noise_texture() {
+        let black = Vec3::from_float(0.0);
+        let white = Vec3::from_float(1.0);
+        let p = Vec3::new(0.37, 1.2, -0.8);
+        for &pattern in &[
+            Pattern::Smooth,
+            Pattern::Turbulence,
+            Pattern::Marble,
+            Pattern::Wood,
+            Pattern::Cells,
+        ] {
+            let texture =
+                NoiseTexture::new(pattern, 4.0, &black, &white, &mut Sampler::from_seed(9));
+            let again = NoiseTexture::new(pattern, 4.0, &black, &white, &mut Sampler::from_seed(9));
+            let color = texture.value(0.0, 0.0, &p);
+            assert_eq!(color, again.value(0.0, 0.0, &p));
+            //the blend lands between the two colors
+            assert!((0.0..=1.0).contains(&color.x()));
+            assert_eq!(color.x(), color.z());
+            //and varies over space
+            let values: Vec<f32> = (0..50)
+                .map(|i| texture.blend(&Vec3::new(i as f32 * 0.13, 0.5, i as f32 * 0.07)))
+                .collect();
+            assert!(
+                values.iter().any(|v| (v - values[0]).abs() > 0.05),
+                "{:?}",
+                pattern
+            );
+        }
+    }
+}
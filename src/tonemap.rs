@@ -0,0 +1,233 @@
+//! Exposure, tone mapping and transfer curves applied before 8 bit output.
+
+use super::vec3::*;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/// how radiance above 1.0 is compressed into the displayable range
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneOperator {
+    /// values above 1.0 are clipped
+    Clamp,
+    /// luminance based x / (1 + x), keeps hue
+    Reinhard,
+    /// narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// john hable's filmic curve from uncharted 2
+    Hable,
+}
+
+impl ToneOperator {
+    pub fn apply(&self, color: &Vec3) -> Vec3 {
+        match self {
+            ToneOperator::Clamp => *color,
+            ToneOperator::Reinhard => {
+                let luminance = luminance(color);
+                if luminance <= 0.0 {
+                    return *color;
+                }
+                color * (1.0 / (1.0 + luminance))
+            }
+            //the fit overshoots 1.0 slightly for very bright input
+            ToneOperator::Aces => map_channels(color, |x| {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.0)
+            }),
+            ToneOperator::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                let white_scale = 1.0 / hable(WHITE);
+                //everything brighter than the white point is clipped
+                map_channels(color, |x| (hable(x * EXPOSURE_BIAS) * white_scale).min(1.0))
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// the curve used to encode linear values for an 8 bit display
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "TransferDescription")]
+pub enum Transfer {
+    Srgb,
+    /// a pure power curve, e.g. 2.2
+    Gamma(f32),
+    Linear,
+}
+
+impl Transfer {
+    pub fn encode(&self, linear: f32) -> f32 {
+        let linear = linear.clamp(0.0, 1.0);
+        match self {
+            Transfer::Srgb => srgb_encode(linear),
+            Transfer::Gamma(gamma) => linear.powf(1.0 / gamma),
+            Transfer::Linear => linear,
+        }
+    }
+}
+
+//a transfer is written either as a name or as a gamma exponent
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TransferDescription {
+    Name(String),
+    Gamma(f32),
+}
+
+impl TryFrom<TransferDescription> for Transfer {
+    type Error = String;
+    fn try_from(description: TransferDescription) -> Result<Self, Self::Error> {
+        match description {
+            TransferDescription::Name(name) => match name.as_str() {
+                "srgb" => Ok(Transfer::Srgb),
+                "linear" => Ok(Transfer::Linear),
+                _ => Err(format!(
+                    "unknown transfer '{}', expected \"srgb\", \"linear\" or a gamma exponent",
+                    name
+                )),
+            },
+            TransferDescription::Gamma(gamma) if gamma > 0.0 => Ok(Transfer::Gamma(gamma)),
+            TransferDescription::Gamma(gamma) => {
+                Err(format!("gamma must be positive, got {}", gamma))
+            }
+        }
+    }
+}
+
+/// post processing applied to linear radiance before it is quantized to 8 bits
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Tonemap {
+    /// in stops, each one doubles the brightness
+    pub exposure: f32,
+    pub operator: ToneOperator,
+    pub transfer: Transfer,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Tonemap {
+            exposure: 0.0,
+            operator: ToneOperator::Clamp,
+            transfer: Transfer::Srgb,
+        }
+    }
+}
+
+impl Tonemap {
+    /// maps linear radiance to encoded display values in [0, 1]
+    pub fn map(&self, color: &Vec3) -> Vec3 {
+        //negative and NaN radiance can come out of a bad sample, show it as black rather than garbage
+        let color = map_channels(color, |x| if x > 0.0 { x } else { 0.0 });
+        let exposed = &color * 2f32.powf(self.exposure);
+        let toned = self.operator.apply(&exposed);
+        map_channels(&toned, |x| self.transfer.encode(x))
+    }
+
+    pub fn quantize(&self, color: &Vec3) -> [u8; 3] {
+        let mapped = self.map(color);
+        let quantize = |x: f32| (x * 255.0 + 0.5) as u8;
+        [
+            quantize(mapped.r()),
+            quantize(mapped.g()),
+            quantize(mapped.b()),
+        ]
+    }
+}
+
+/// rec. 709 luminance of linear rgb
+pub fn luminance(color: &Vec3) -> f32 {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+/// the sRGB transfer function, input is clamped to [0, 1]
+pub fn srgb_encode(linear: f32) -> f32 {
+    let linear = linear.clamp(0.0, 1.0);
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// the inverse of `srgb_encode`, for reading 8 bit images back into linear values
+pub fn srgb_decode(encoded: f32) -> f32 {
+    let encoded = encoded.clamp(0.0, 1.0);
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn map_channels<F: Fn(f32) -> f32>(color: &Vec3, f: F) -> Vec3 {
+    Vec3::new(f(color.r()), f(color.g()), f(color.b()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_operators_compress_highlights() {
+        let operators = [
+            ToneOperator::Reinhard,
+            ToneOperator::Aces,
+            ToneOperator::Hable,
+        ];
+        for operator in operators.iter() {
+            let mut previous = -1.0;
+            for i in 0..100 {
+                let x = i as f32 * 0.5;
+                let y = operator.apply(&Vec3::from_float(x)).g();
+                assert!(y >= previous, "{:?} is not monotonic", operator);
+                assert!(y <= 1.0 + 1e-4, "{:?} maps {} to {}", operator, x, y);
+                previous = y;
+            }
+            assert!(operator.apply(&Vec3::from_float(0.0)).g().abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_exposure_and_transfer() {
+        let linear = Tonemap {
+            exposure: 1.0,
+            operator: ToneOperator::Clamp,
+            transfer: Transfer::Linear,
+        };
+        assert_eq!(linear.map(&Vec3::from_float(0.25)), Vec3::from_float(0.5));
+        assert_eq!(
+            linear.quantize(&Vec3::new(4.0, f32::NAN, -1.0)),
+            [255, 0, 0]
+        );
+        let gamma = Tonemap {
+            transfer: Transfer::Gamma(2.0),
+            ..Tonemap::default()
+        };
+        assert_eq!(gamma.map(&Vec3::from_float(0.25)), Vec3::from_float(0.5));
+        assert_eq!(
+            Tonemap::default().quantize(&Vec3::new(1.0, 0.5, 0.2)),
+            [255, 188, 124]
+        );
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for i in 0..=255 {
+            let encoded = i as f32 / 255.0;
+            let linear = srgb_decode(encoded);
+            assert!((srgb_encode(linear) - encoded).abs() < 1e-5);
+        }
+        //mid grey in an 8 bit file is about a fifth in linear light
+        assert!((srgb_decode(0.5) - 0.214).abs() < 1e-3);
+    }
+}
@@ -1,13 +1,17 @@
+//! The 3d vector type used for points, directions and colors.
+
 use std::ops::Add;
 use std::ops::AddAssign;
 use std::ops::Div;
 use std::ops::DivAssign;
+use std::ops::Index;
 use std::ops::Mul;
 use std::ops::MulAssign;
 use std::ops::Neg;
 use std::ops::Sub;
 use std::ops::SubAssign;
 
+/// a point, direction or linear rgb color
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3 {
     x: f32,
@@ -15,7 +19,8 @@ pub struct Vec3 {
     z: f32,
 }
 
-type Color = Vec3;
+/// linear rgb, stored in x, y and z
+pub type Color = Vec3;
 
 impl Vec3 {
     pub fn new(e1: f32, e2: f32, e3: f32) -> Self {
@@ -71,6 +76,20 @@ impl Vec3 {
     pub fn dot(&self, other: &Vec3) -> f32 {
         (self * other).sum()
     }
+    pub fn min(&self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+    pub fn max(&self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
     pub fn project(&self, onto: &Vec3) -> Vec3 {
         onto * (self.dot(onto) / onto.squared_length())
     }
@@ -96,6 +115,66 @@ impl Vec3 {
             radius * phi.cos(),
         )
     }
+
+    /// two unit vectors that make a right handed orthonormal basis with this unit vector
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        //duff et al. 2017, branchless apart from the sign and stable all the way round the sphere
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+}
+
+/// an orthonormal frame around a surface normal, tangent x bitangent = normal unless the
+/// bitangent was flipped to follow a mirrored uv layout
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Onb {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+impl Onb {
+    /// any frame around the unit normal
+    pub fn from_normal(normal: &Vec3) -> Self {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Onb {
+            tangent,
+            bitangent,
+            normal: *normal,
+        }
+    }
+    /// the frame around the unit normal whose tangent is closest to tangent
+    pub fn from_normal_tangent(normal: &Vec3, tangent: &Vec3) -> Self {
+        //gram-schmidt, a tangent along the normal says nothing so any frame will do
+        let tangent = tangent - &(normal * normal.dot(tangent));
+        let length = tangent.squared_length();
+        if length.is_nan() || length <= 1e-12 {
+            return Onb::from_normal(normal);
+        }
+        let tangent = tangent.normalize();
+        Onb {
+            tangent,
+            bitangent: normal.cross(&tangent),
+            normal: *normal,
+        }
+    }
+    /// the world direction of a vector given in (tangent, bitangent, normal) coordinates
+    pub fn to_world(&self, local: &Vec3) -> Vec3 {
+        &(&(&self.tangent * local.x) + &(&self.bitangent * local.y)) + &(&self.normal * local.z)
+    }
+    /// the (tangent, bitangent, normal) coordinates of a world direction
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
 }
 
 impl Add<f32> for &Vec3 {
@@ -246,6 +325,18 @@ impl DivAssign<&Vec3> for Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl Neg for Vec3 {
     type Output = Vec3;
     fn neg(self) -> Vec3 {
@@ -281,4 +372,30 @@ mod test {
         let temp2 = Vec3::new(0.0, 1.0, 0.0);
         assert_eq!(temp1.cross(&temp2), Vec3::new(0.0, 0.0, 1.0));
     }
+
+    #[test]
+    fn test_onb() {
+        for normal in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 2.0, -3.0).normalize(),
+            Vec3::new(-0.3, 0.1, 0.0).normalize(),
+        ] {
+            let frame = Onb::from_normal(&normal);
+            assert!((&frame.tangent.cross(&frame.bitangent) - &normal).length() < 1e-6);
+            assert!(frame.tangent.dot(&normal).abs() < 1e-6);
+            assert!((frame.tangent.length() - 1.0).abs() < 1e-6);
+            let v = Vec3::new(0.2, -0.7, 0.4);
+            assert!((&frame.to_world(&frame.to_local(&v)) - &v).length() < 1e-6);
+        }
+        //the tangent is kept as far as it is perpendicular to the normal
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let frame = Onb::from_normal_tangent(&normal, &Vec3::new(2.0, 1.0, 0.0));
+        assert_eq!(frame.tangent, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(frame.bitangent, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(
+            Onb::from_normal_tangent(&normal, &normal),
+            Onb::from_normal(&normal)
+        );
+    }
 }
@@ -0,0 +1,453 @@
+//! Participating media such as fog, smoke and clouds that scatter light inside a volume.
+//!
+//! Density grids are read from a small binary format, all numbers little endian:
+//!
+//! | bytes | contents |
+//! |-------|----------|
+//! | 4 | the magic `GRID` |
+//! | 12 | the grid size `nx`, `ny`, `nz` as `u32` |
+//! | `4 * nx * ny * nz` | densities as `f32`, x varying fastest, then y, then z |
+//!
+//! The samples sit on a lattice spanning the unit cube corner to corner and are scaled to the
+//! box of the volume that uses them.
+
+use super::aabb::*;
+use super::materials::*;
+use super::ray::*;
+use super::sampler::*;
+use super::shapes::*;
+use super::vec3::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// a medium of uniform density filling a closed boundary shape
+///
+/// rays pass straight through or scatter at a random distance inside, how far they get is
+/// exponentially distributed with a mean free path of 1 / density
+#[derive(Debug)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Intersect>,
+    density: f32,
+    phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// phase is usually `Isotropic`, the material of the boundary itself is never used
+    pub fn new(boundary: Arc<dyn Intersect>, density: f32, phase: Arc<dyn Material>) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            phase,
+        }
+    }
+    pub fn boundary(&self) -> &Arc<dyn Intersect> {
+        &self.boundary
+    }
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+}
+
+impl Intersect for ConstantMedium {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        //find where the ray enters and leaves, the entry may be behind a ray that starts inside
+        let entry = self
+            .boundary
+            .intersect(ray, f32::NEG_INFINITY, f32::INFINITY, sampler)?
+            .distance;
+        let exit = self
+            .boundary
+            .intersect(ray, entry + 0.0001, f32::INFINITY, sampler)?
+            .distance;
+        let entry = entry.max(t_min);
+        let exit = exit.min(t_max);
+        if entry >= exit {
+            return None;
+        }
+        let u = sampler.gen();
+        let distance = entry - (1.0 - u).ln() / self.density;
+        if distance >= exit {
+            return None;
+        }
+        Some(Hit::new(&ray.parameterization(distance), distance, self))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+impl Normal for ConstantMedium {
+    /// scattering inside a medium has no surface, so any direction will do
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &Vec3::new(1.0, 0.0, 0.0))
+    }
+    fn material(&self) -> &dyn Material {
+        &*self.phase
+    }
+}
+
+/// non-negative densities on an nx x ny x nz lattice over the unit cube
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityGrid {
+    size: [usize; 3],
+    densities: Vec<f32>,
+    max: f32,
+}
+
+const GRID_MAGIC: &[u8; 4] = b"GRID";
+
+impl DensityGrid {
+    /// panics unless there are nx * ny * nz finite, non-negative densities
+    pub fn new(size: [usize; 3], densities: Vec<f32>) -> Self {
+        assert!(size.iter().all(|&n| n > 0), "grid sizes must be positive");
+        assert_eq!(
+            densities.len(),
+            size[0] * size[1] * size[2],
+            "density count must be nx * ny * nz"
+        );
+        assert!(
+            densities.iter().all(|d| d.is_finite() && *d >= 0.0),
+            "densities must be finite and non-negative"
+        );
+        let max = densities.iter().cloned().fold(0.0, f32::max);
+        DensityGrid {
+            size,
+            densities,
+            max,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<DensityGrid> {
+        DensityGrid::read(&fs::read(path)?)
+    }
+
+    /// parses the format described at the top of this module
+    pub fn read(data: &[u8]) -> io::Result<DensityGrid> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if data.len() < 16 || &data[0..4] != GRID_MAGIC {
+            return Err(invalid("not a density grid, expected the magic 'GRID'"));
+        }
+        let word = |i: usize| {
+            let bytes = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+            u32::from_le_bytes(bytes)
+        };
+        let size = [word(4) as usize, word(8) as usize, word(12) as usize];
+        if size.contains(&0) {
+            return Err(invalid("grid sizes must be positive"));
+        }
+        let count = size
+            .iter()
+            .try_fold(1usize, |count, &n| count.checked_mul(n))
+            .ok_or_else(|| invalid("grid is too large"))?;
+        let samples = &data[16..];
+        if samples.len() / 4 < count {
+            return Err(invalid("grid has fewer densities than its size"));
+        }
+        let densities: Vec<f32> = samples
+            .chunks_exact(4)
+            .take(count)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        if !densities.iter().all(|d| d.is_finite() && *d >= 0.0) {
+            return Err(invalid("densities must be finite and non-negative"));
+        }
+        Ok(DensityGrid::new(size, densities))
+    }
+
+    /// the file contents read accepts
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(16 + 4 * self.densities.len());
+        data.extend_from_slice(GRID_MAGIC);
+        for n in &self.size {
+            data.extend_from_slice(&(*n as u32).to_le_bytes());
+        }
+        for density in &self.densities {
+            data.extend_from_slice(&density.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.max
+    }
+
+    pub fn get(&self, i: usize, j: usize, k: usize) -> f32 {
+        self.densities[(k * self.size[1] + j) * self.size[0] + i]
+    }
+
+    /// trilinear interpolation at a point of the unit cube, outside it the nearest face is used
+    pub fn sample(&self, p: &Vec3) -> f32 {
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut weight = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.size[axis];
+            let x = p[axis].clamp(0.0, 1.0) * (n - 1) as f32;
+            lower[axis] = (x.floor() as usize).min(n - 1);
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            weight[axis] = x - lower[axis] as f32;
+        }
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner & (1 << axis) != 0;
+            let index = |axis: usize| if pick(axis) { upper[axis] } else { lower[axis] };
+            let w = |axis: usize| {
+                if pick(axis) {
+                    weight[axis]
+                } else {
+                    1.0 - weight[axis]
+                }
+            };
+            density += w(0) * w(1) * w(2) * self.get(index(0), index(1), index(2));
+        }
+        density
+    }
+}
+
+/// a medium whose density is read from a grid stretched over an axis aligned box
+///
+/// the coefficients are per unit of length at density 1, so the medium absorbs
+/// absorption * density and scatters scattering * density
+#[derive(Debug)]
+pub struct GridVolume {
+    grid: Arc<DensityGrid>,
+    bounds: Aabb,
+    extinction: f32,
+    //an upper bound on the extinction anywhere in the box
+    majorant: f32,
+    phase: HenyeyGreenstein,
+}
+
+impl GridVolume {
+    /// g shapes the Henyey-Greenstein phase function, 0 scatters evenly in every direction
+    pub fn new(
+        grid: Arc<DensityGrid>,
+        min: &Vec3,
+        max: &Vec3,
+        absorption: f32,
+        scattering: f32,
+        g: f32,
+    ) -> Self {
+        let extinction = absorption + scattering;
+        //the chance that a collision scatters rather than absorbs
+        let albedo = if extinction > 0.0 {
+            scattering / extinction
+        } else {
+            0.0
+        };
+        GridVolume {
+            majorant: extinction * grid.max_density(),
+            grid,
+            bounds: Aabb::new(min, max),
+            extinction,
+            phase: HenyeyGreenstein::new(&Vec3::from_float(albedo), g),
+        }
+    }
+
+    pub fn grid(&self) -> &Arc<DensityGrid> {
+        &self.grid
+    }
+
+    /// the grid density at a point in world space
+    pub fn density(&self, point: &Vec3) -> f32 {
+        let local = &(point - self.bounds.min()) / &self.bounds.extent();
+        self.grid.sample(&local)
+    }
+}
+
+impl Intersect for GridVolume {
+    fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        sampler: &mut Sampler,
+    ) -> Option<Hit<'_>> {
+        let (mut t, end) = self.bounds.clip(ray, t_min, t_max)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        //delta tracking: step through a medium as dense as the majorant everywhere and accept
+        //each tentative collision with the chance that it is real rather than null
+        loop {
+            t -= (1.0 - sampler.gen()).ln() / self.majorant;
+            if t >= end {
+                return None;
+            }
+            let point = ray.parameterization(t);
+            let extinction = self.extinction * self.density(&point);
+            if sampler.gen() * self.majorant < extinction {
+                return Some(Hit::new(&point, t, self));
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl Normal for GridVolume {
+    /// scattering inside a medium has no surface, so any direction will do
+    fn normal(&self, point: &Vec3) -> Ray {
+        Ray::new(point, &Vec3::new(1.0, 0.0, 0.0))
+    }
+    fn material(&self) -> &dyn Material {
+        &self.phase
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_constant_medium() {
+        let mut sampler = Sampler::from_seed(0);
+        let white: Arc<dyn Material> = Arc::new(Isotropic::new(&Vec3::from_float(1.0)));
+        let ball: Arc<dyn Intersect> =
+            Arc::new(Sphere::new(&Vec3::new(0.0, 0.0, 0.0), 1.0, white.clone()));
+        let fog = ConstantMedium::new(ball.clone(), 2.0, white);
+        assert_eq!(fog.bounding_box(), ball.bounding_box());
+        //across the diameter of 2 the chance of passing through is exp(-2 * 2)
+        let trials = 4000;
+        let mut passed = 0;
+        let mut total = 0.0;
+        for i in 0..trials {
+            let y = (i as f32 / trials as f32 - 0.5) * 1e-3;
+            let ray = Ray::new(&Vec3::new(0.0, y, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+            match fog.intersect(&ray, 0.001, f32::INFINITY, &mut sampler) {
+                Some(hit) => {
+                    assert!(hit.distance >= 4.0 - 1e-3 && hit.distance < 6.0 + 1e-3);
+                    total += hit.distance - 4.0;
+                }
+                None => passed += 1,
+            }
+        }
+        let expected = (-4.0f32).exp();
+        assert!((passed as f32 / trials as f32 - expected).abs() < 0.01);
+        //the mean depth of a scatter, given one happens, is about 1 / density
+        let mean = total / (trials - passed) as f32;
+        assert!((mean - 0.46).abs() < 0.05, "{}", mean);
+        //a ray starting inside scatters within reach of where it starts
+        let inside = Ray::new(&Vec3::new(0.0, 0.0, 0.0), &Vec3::new(0.0, 1.0, 0.0));
+        if let Some(hit) = fog.intersect(&inside, 0.001, f32::INFINITY, &mut sampler) {
+            assert!(hit.distance > 0.0 && hit.distance < 1.0);
+        }
+        //nothing to hit beside the ball
+        let beside = Ray::new(&Vec3::new(2.0, 0.0, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+        assert!(fog
+            .intersect(&beside, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+    }
+
+    #[test]
+    fn test_density_grid() {
+        //x varies fastest: densities 0..8 on a 2x2x2 lattice
+        let grid = DensityGrid::new([2, 2, 2], (0..8).map(|d| d as f32).collect());
+        assert_eq!(grid.get(1, 0, 0), 1.0);
+        assert_eq!(grid.get(0, 1, 1), 6.0);
+        assert_eq!(grid.max_density(), 7.0);
+        assert_eq!(grid.sample(&Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(grid.sample(&Vec3::new(1.0, 1.0, 1.0)), 7.0);
+        assert!((grid.sample(&Vec3::from_float(0.5)) - 3.5).abs() < 1e-6);
+        assert!((grid.sample(&Vec3::new(0.25, 0.0, 1.0)) - 4.25).abs() < 1e-6);
+        assert_eq!(grid.sample(&Vec3::new(-1.0, 0.0, 2.0)), 4.0);
+
+        let bytes = grid.to_bytes();
+        assert_eq!(&bytes[0..4], b"GRID");
+        assert_eq!(bytes.len(), 16 + 4 * 8);
+        assert_eq!(DensityGrid::read(&bytes).unwrap(), grid);
+        let error = DensityGrid::read(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("fewer densities"), "{}", error);
+        let error = DensityGrid::read(b"PGM 2 2 2").unwrap_err();
+        assert!(error.to_string().contains("magic"), "{}", error);
+        let mut negative = bytes.clone();
+        negative[16..20].copy_from_slice(&(-1.0f32).to_le_bytes());
+        assert!(DensityGrid::read(&negative).is_err());
+    }
+
+    #[test]
+    fn test_grid_volume() {
+        //uniform density 1 in a 2 unit cube with extinction 1, so exp(-2) gets through
+        let grid = Arc::new(DensityGrid::new([2, 2, 2], vec![1.0; 8]));
+        let volume = GridVolume::new(
+            grid,
+            &Vec3::from_float(-1.0),
+            &Vec3::from_float(1.0),
+            0.25,
+            0.75,
+            0.0,
+        );
+        let trials = 4000;
+        let mut passed = 0;
+        let mut sampler = Sampler::from_seed(7);
+        for i in 0..trials {
+            let y = (i as f32 / trials as f32 - 0.5) * 1e-3;
+            let ray = Ray::new(&Vec3::new(0.0, y, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+            match volume.intersect(&ray, 0.001, f32::INFINITY, &mut sampler) {
+                Some(hit) => {
+                    assert!(hit.distance >= 4.0 && hit.distance < 6.0);
+                    //three quarters of all collisions scatter
+                    let scattered = hit.scatter(&ray, &mut sampler).unwrap();
+                    assert!((scattered.attenuation.x() - 0.75).abs() < 1e-6);
+                }
+                None => passed += 1,
+            }
+        }
+        let expected = (-2.0f32).exp();
+        assert!((passed as f32 / trials as f32 - expected).abs() < 0.015);
+
+        //an empty grid never scatters
+        let empty = GridVolume::new(
+            Arc::new(DensityGrid::new([1, 1, 1], vec![0.0])),
+            &Vec3::from_float(-1.0),
+            &Vec3::from_float(1.0),
+            1.0,
+            1.0,
+            0.0,
+        );
+        let ray = Ray::new(&Vec3::new(0.0, 0.0, -5.0), &Vec3::new(0.0, 0.0, 1.0));
+        assert!(empty
+            .intersect(&ray, 0.001, f32::INFINITY, &mut sampler)
+            .is_none());
+    }
+
+    #[test]
+    fn test_henyey_greenstein() {
+        for &g in &[-0.5, 0.0, 0.8] {
+            let phase = HenyeyGreenstein::new(&Vec3::from_float(1.0), g);
+            //the phase function integrates to one over the sphere
+            let steps = 2000;
+            let integral: f32 = (0..steps)
+                .map(|i| {
+                    let cos_theta = -1.0 + 2.0 * (i as f32 + 0.5) / steps as f32;
+                    phase.phase(cos_theta) * 2.0 * std::f32::consts::PI * 2.0 / steps as f32
+                })
+                .sum();
+            assert!((integral - 1.0).abs() < 0.01, "{} {}", g, integral);
+            //and the mean cosine of sampled directions is g
+            let mut sampler = Sampler::from_seed(3);
+            let n = 20000;
+            let mean = (0..n)
+                .map(|_| phase.sample_cos_theta(&mut sampler))
+                .sum::<f32>()
+                / n as f32;
+            assert!((mean - g).abs() < 0.02, "{} {}", g, mean);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use ray_tracing_in_a_weekend::camera::Camera;
+use ray_tracing_in_a_weekend::materials::{DiffuseLight, Lambertian};
+use ray_tracing_in_a_weekend::render::render;
+use ray_tracing_in_a_weekend::scene::{Background, RenderSettings, Scene};
+use ray_tracing_in_a_weekend::shapes::Sphere;
+use ray_tracing_in_a_weekend::vec3::Vec3;
+use std::ffi::OsStr;
+use std::fs;
+use std::thread;
+
+#[test]
+fn test_scene_built_in_code() {
+    let settings = RenderSettings {
+        width: 20,
+        height: 10,
+        samples: 4,
+        max_depth: 4,
+        background: Background::Color(Vec3::from_float(0.0)),
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(
+        &Vec3::new(0.0, 0.0, 1.0),
+        &Vec3::new(0.0, 0.0, -1.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+        60.0,
+        2.0,
+        0.0,
+    );
+    let mut scene = Scene::new(settings, camera);
+    let light = scene.add_material("light", DiffuseLight::new(&Vec3::from_float(4.0)));
+    let floor = scene.add_material("floor", Lambertian::new(&Vec3::from_float(0.5)));
+    scene.add(Sphere::new(&Vec3::new(0.0, 0.0, -1.0), 0.5, light));
+    scene.add(Sphere::new(&Vec3::new(0.0, -100.5, -1.0), 100.0, floor));
+    //the scene owns everything it needs so it can be handed to another thread
+    let framebuffer = thread::spawn(move || {
+        render(
+            &scene.settings,
+            &scene.camera,
+            &scene.world(),
+            &scene.lights(),
+        )
+    })
+    .join()
+    .unwrap();
+    //the lamp fills the middle of the image and nothing lights the corners
+    assert_eq!(*framebuffer.get(10, 5), Vec3::from_float(4.0));
+    assert_eq!(*framebuffer.get(0, 0), Vec3::from_float(0.0));
+}
+
+#[test]
+fn test_example_scenes_load() {
+    let mut count = 0;
+    for entry in fs::read_dir("scenes").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some(OsStr::new("toml")) {
+            let scene = Scene::load(&path).unwrap_or_else(|e| panic!("{}", e));
+            assert!(!scene.world().is_empty(), "{}", path.display());
+            count += 1;
+        }
+    }
+    assert!(count > 0);
+}